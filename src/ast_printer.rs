@@ -0,0 +1,289 @@
+use crate::expr::{AND, Assignment, Binary, Block, BreakStmt, BreakpointStmt, Call, ClassDecl, Coalesce, Conditional, ContinueStmt, DeferStmt, DoWhileStmt, EnumDecl,
+    Expr, ExprVisitor, Expression, ForStmt, FunctionStmt, Get, Grouping, IfStatement, IncrStmt, Index, IndexSet, Interpolation, ListExpr, MapExpr, Lambda,
+    Literal, OR, Print, ReturnStmt, Set, Stmt, StmtVisitor, Super, SwitchStmt, AssertStmt, This, Unary, VarDecl, Variable, WhileStmt, YieldStmt};
+use crate::token::{LiteralType, Token};
+
+/// Renders the syntax tree as Lisp-style text, e.g. `(* (- 1) (group 2))`.
+/// Used by the `--ast` flag to debug the parser.
+pub struct AstPrinter;
+
+impl AstPrinter {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// One line per top-level statement
+    pub fn print(&mut self, statements: &[Stmt]) -> String {
+        statements.iter().map(|stmt| stmt.accept(self)).collect::<Vec<_>>().join("\n")
+    }
+
+    pub fn print_expr(&mut self, expr: &Expr) -> String {
+        expr.accept(self)
+    }
+
+    /// Helper: `(name part part ...)`
+    fn parenthesize(name: &str, parts: &[String]) -> String {
+        let mut text = format!("({}", name);
+        for part in parts {
+            text.push(' ');
+            text.push_str(part);
+        }
+        text.push(')');
+        text
+    }
+
+    fn statements(&mut self, statements: &[Stmt]) -> Vec<String> {
+        statements.iter().map(|stmt| stmt.accept(self)).collect()
+    }
+
+    /// Parameters with a default are shown as `(= name default)`, a rest parameter as `...name`
+    fn params(&mut self, params: &[Token], defaults: &[Expr], is_variadic: bool) -> String {
+        let fixed = params.len() - is_variadic as usize;
+        let first_default = fixed - defaults.len();
+        let mut names: Vec<String> = params[..first_default].iter().map(|param| param.lexeme.clone()).collect();
+        for (param, default) in params[first_default..fixed].iter().zip(defaults) {
+            names.push(Self::parenthesize("=", &[param.lexeme.clone(), self.print_expr(default)]));
+        }
+        if is_variadic {
+            names.push(format!("...{}", params[fixed].lexeme));
+        }
+        format!("({})", names.join(" "))
+    }
+
+    /// A labeled loop is shown as `(name: (while ...))`
+    fn labeled(label: &Option<Token>, loop_text: String) -> String {
+        match label {
+            Some(label) => Self::parenthesize(&format!("{}:", label.lexeme), &[loop_text]),
+            None => loop_text,
+        }
+    }
+
+    /// `(break)` or `(break name)`
+    fn jump(keyword: &str, label: &Option<Token>) -> String {
+        let parts: Vec<String> = label.iter().map(|label| label.lexeme.clone()).collect();
+        Self::parenthesize(keyword, &parts)
+    }
+
+    fn function(&mut self, keyword: &str, function: &FunctionStmt) -> String {
+        let mut parts = vec![function.name.lexeme.clone()];
+        if !function.is_getter {
+            parts.push(self.params(&function.params, &function.defaults, function.is_variadic));
+        }
+        parts.extend(self.statements(&function.body));
+        Self::parenthesize(keyword, &parts)
+    }
+}
+
+impl StmtVisitor<String> for AstPrinter {
+    fn visit_print_stmt(&mut self, stmt: &Print) -> String {
+        let values: Vec<String> = stmt.expressions.iter().map(|expression| self.print_expr(expression)).collect();
+        Self::parenthesize("print", &values)
+    }
+    fn visit_expression_stmt(&mut self, stmt: &Expression) -> String {
+        Self::parenthesize(";", &[self.print_expr(&stmt.expression)])
+    }
+    fn visit_var_decl(&mut self, var_decl: &VarDecl) -> String {
+        let mut parts = vec![var_decl.name.lexeme.clone()];
+        parts.extend(var_decl.unpack.iter().map(|name| name.lexeme.clone()));
+        if let Some(initializer) = &var_decl.initializer {
+            parts.push(self.print_expr(initializer));
+        }
+        Self::parenthesize("var", &parts)
+    }
+    fn visit_block_stmt(&mut self, block: &Block) -> String {
+        Self::parenthesize("block", &self.statements(&block.statements))
+    }
+    fn visit_if_stmt(&mut self, if_stmt: &IfStatement) -> String {
+        let mut parts = vec![self.print_expr(&if_stmt.condition), if_stmt.then_branch.accept(self)];
+        if let Some(else_branch) = &if_stmt.else_branch {
+            parts.push(else_branch.accept(self));
+        }
+        Self::parenthesize("if", &parts)
+    }
+    fn visit_while_stmt(&mut self, while_stmt: &WhileStmt) -> String {
+        let text = Self::parenthesize("while", &[self.print_expr(&while_stmt.condition), while_stmt.body.accept(self)]);
+        Self::labeled(&while_stmt.label, text)
+    }
+    fn visit_do_while_stmt(&mut self, do_while_stmt: &DoWhileStmt) -> String {
+        let text = Self::parenthesize("do", &[do_while_stmt.body.accept(self), self.print_expr(&do_while_stmt.condition)]);
+        Self::labeled(&do_while_stmt.label, text)
+    }
+    fn visit_for_stmt(&mut self, for_stmt: &ForStmt) -> String {
+        // 省略された節は nil と表示する
+        let parts = [
+            for_stmt.initializer.as_ref().map_or("nil".to_string(), |stmt| stmt.accept(self)),
+            for_stmt.condition.as_ref().map_or("nil".to_string(), |expr| self.print_expr(expr)),
+            for_stmt.increment.as_ref().map_or("nil".to_string(), |expr| self.print_expr(expr)),
+            for_stmt.body.accept(self),
+        ];
+        Self::labeled(&for_stmt.label, Self::parenthesize("for", &parts))
+    }
+    fn visit_break_stmt(&mut self, break_stmt: &BreakStmt) -> String {
+        Self::jump("break", &break_stmt.label)
+    }
+    fn visit_continue_stmt(&mut self, continue_stmt: &ContinueStmt) -> String {
+        Self::jump("continue", &continue_stmt.label)
+    }
+    fn visit_breakpoint_stmt(&mut self, _breakpoint_stmt: &BreakpointStmt) -> String {
+        "(breakpoint)".to_string()
+    }
+    fn visit_function_stmt(&mut self, function_stmt: &FunctionStmt) -> String {
+        self.function("fun", function_stmt)
+    }
+    fn visit_return_stmt(&mut self, return_stmt: &ReturnStmt) -> String {
+        match &return_stmt.value {
+            Some(value) => Self::parenthesize("return", &[self.print_expr(value)]),
+            None => "(return)".to_string(),
+        }
+    }
+    fn visit_yield_stmt(&mut self, yield_stmt: &YieldStmt) -> String {
+        Self::parenthesize("yield", &[self.print_expr(&yield_stmt.value)])
+    }
+    fn visit_incr_stmt(&mut self, incr_stmt: &IncrStmt) -> String {
+        let mut parts = vec![incr_stmt.target.name.lexeme.clone()];
+        if let Some(amount) = &incr_stmt.amount {
+            parts.push(self.print_expr(amount));
+        }
+        Self::parenthesize(&incr_stmt.keyword.lexeme, &parts)
+    }
+    fn visit_class_decl(&mut self, class_decl: &ClassDecl) -> String {
+        let mut parts = vec![class_decl.name.lexeme.clone()];
+        if let Some(superclass) = &class_decl.superclass {
+            parts.push(format!("(< {})", superclass.name.lexeme));
+        }
+        for method in &class_decl.methods {
+            let keyword = if method.is_getter { "get" } else { "method" };
+            parts.push(self.function(keyword, method));
+        }
+        for method in &class_decl.class_methods {
+            parts.push(self.function("static", method));
+        }
+        Self::parenthesize("class", &parts)
+    }
+    fn visit_assert_stmt(&mut self, assert_stmt: &AssertStmt) -> String {
+        let mut parts = vec![self.print_expr(&assert_stmt.condition)];
+        if let Some(message) = &assert_stmt.message {
+            parts.push(self.print_expr(message));
+        }
+        Self::parenthesize("assert", &parts)
+    }
+    fn visit_defer_stmt(&mut self, defer_stmt: &DeferStmt) -> String {
+        Self::parenthesize("defer", &[defer_stmt.body.accept(self)])
+    }
+    fn visit_switch_stmt(&mut self, switch_stmt: &SwitchStmt) -> String {
+        let mut parts = vec![self.print_expr(&switch_stmt.discriminant)];
+        for (value, body) in &switch_stmt.cases {
+            let mut arm = vec![self.print_expr(value)];
+            arm.extend(self.statements(body));
+            parts.push(Self::parenthesize("case", &arm));
+        }
+        if let Some(body) = &switch_stmt.default {
+            parts.push(Self::parenthesize("default", &self.statements(body)));
+        }
+        Self::parenthesize("switch", &parts)
+    }
+    fn visit_enum_decl(&mut self, enum_decl: &EnumDecl) -> String {
+        let mut parts = vec![enum_decl.name.lexeme.clone()];
+        parts.extend(enum_decl.variants.iter().map(|variant| variant.lexeme.clone()));
+        Self::parenthesize("enum", &parts)
+    }
+}
+
+impl ExprVisitor<String> for AstPrinter {
+    fn visit_binary_expr(&mut self, expr: &Binary) -> String {
+        Self::parenthesize(&expr.operator.lexeme, &[self.print_expr(&expr.left), self.print_expr(&expr.right)])
+    }
+    fn visit_grouping_expr(&mut self, expr: &Grouping) -> String {
+        Self::parenthesize("group", &[self.print_expr(&expr.expression)])
+    }
+    fn visit_literal_expr(&mut self, expr: &Literal) -> String {
+        match &expr.value {
+            LiteralType::String(s) => format!("{:?}", s),
+            LiteralType::Number(n) => {
+                let mut text = n.to_string();
+                if text.ends_with(".0") {
+                    text.truncate(text.len() - 2);
+                }
+                text
+            }
+            LiteralType::Bool(b) => b.to_string(),
+            LiteralType::Nil => "nil".to_string(),
+        }
+    }
+    fn visit_unary_expr(&mut self, expr: &Unary) -> String {
+        Self::parenthesize(&expr.operator.lexeme, &[self.print_expr(&expr.right)])
+    }
+    fn visit_variable_expr(&mut self, expr: &Variable) -> String {
+        expr.name.lexeme.clone()
+    }
+    fn visit_assignment_expr(&mut self, expr: &Assignment) -> String {
+        Self::parenthesize("=", &[expr.name.lexeme.clone(), self.print_expr(&expr.value)])
+    }
+    fn visit_coalesce_expr(&mut self, expr: &Coalesce) -> String {
+        Self::parenthesize("??", &[self.print_expr(&expr.left), self.print_expr(&expr.right)])
+    }
+    fn visit_or_expr(&mut self, expr: &OR) -> String {
+        Self::parenthesize("or", &[self.print_expr(&expr.left), self.print_expr(&expr.right)])
+    }
+    fn visit_and_expr(&mut self, expr: &AND) -> String {
+        Self::parenthesize("and", &[self.print_expr(&expr.left), self.print_expr(&expr.right)])
+    }
+    fn visit_call_expr(&mut self, expr: &Call) -> String {
+        let mut parts = vec![self.print_expr(&expr.callee)];
+        parts.extend(expr.arguments.iter().map(|argument| self.print_expr(argument)));
+        Self::parenthesize("call", &parts)
+    }
+    fn visit_get_expr(&mut self, expr: &Get) -> String {
+        Self::parenthesize(".", &[self.print_expr(&expr.object), expr.name.lexeme.clone()])
+    }
+    fn visit_set_expr(&mut self, expr: &Set) -> String {
+        let name = match &expr.operator {
+            Some(operator) => format!(".{}=", operator.lexeme),
+            None => ".=".to_string(),
+        };
+        Self::parenthesize(&name, &[self.print_expr(&expr.object), expr.name.lexeme.clone(), self.print_expr(&expr.value)])
+    }
+    fn visit_this_expr(&mut self, _expr: &This) -> String {
+        "this".to_string()
+    }
+    fn visit_super_expr(&mut self, expr: &Super) -> String {
+        Self::parenthesize("super", &[expr.method.lexeme.clone()])
+    }
+    fn visit_conditional_expr(&mut self, expr: &Conditional) -> String {
+        let parts = [
+            self.print_expr(&expr.condition),
+            self.print_expr(&expr.then_branch),
+            self.print_expr(&expr.else_branch),
+        ];
+        Self::parenthesize("?:", &parts)
+    }
+    fn visit_lambda_expr(&mut self, expr: &Lambda) -> String {
+        let mut parts: Vec<String> = expr.name.iter().map(|name| name.lexeme.clone()).collect();
+        parts.push(self.params(&expr.params, &expr.defaults, expr.is_variadic));
+        parts.extend(self.statements(&expr.body));
+        Self::parenthesize("lambda", &parts)
+    }
+    fn visit_list_expr(&mut self, expr: &ListExpr) -> String {
+        let elements: Vec<String> = expr.elements.iter().map(|element| self.print_expr(element)).collect();
+        Self::parenthesize("list", &elements)
+    }
+    fn visit_map_expr(&mut self, expr: &MapExpr) -> String {
+        let mut parts = Vec::new();
+        for (key, value) in &expr.entries {
+            parts.push(self.print_expr(key));
+            parts.push(self.print_expr(value));
+        }
+        Self::parenthesize("map", &parts)
+    }
+    fn visit_index_expr(&mut self, expr: &Index) -> String {
+        Self::parenthesize("[]", &[self.print_expr(&expr.object), self.print_expr(&expr.index)])
+    }
+    fn visit_interpolation_expr(&mut self, expr: &Interpolation) -> String {
+        let parts: Vec<String> = expr.parts.iter().map(|part| self.print_expr(part)).collect();
+        Self::parenthesize("interpolate", &parts)
+    }
+    fn visit_index_set_expr(&mut self, expr: &IndexSet) -> String {
+        let parts = [self.print_expr(&expr.object), self.print_expr(&expr.index), self.print_expr(&expr.value)];
+        Self::parenthesize("[]=", &parts)
+    }
+}
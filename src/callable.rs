@@ -1,7 +1,7 @@
-use crate::token::Value;
+use crate::token::{Value, format_number};
 use crate::error::Result;
 use crate::interpreter::Interpreter;
-use crate::expr::Stmt;
+use crate::expr::{Expr, Stmt};
 use crate::environment::Environment;
 use crate::error::{RuntimeError, Error};
 use std::rc::Rc;
@@ -26,11 +26,28 @@ pub trait Callable {
     fn name(&self) -> &str;
 }
 
+/// Body of a native function
+#[derive(Clone)]
+pub enum NativeBody {
+    /// Depends only on its arguments: a builtin `fn` or a closure registered by the host
+    Plain(Rc<dyn Fn(Vec<Value>) -> Result<Value>>),
+    /// Needs the interpreter to call back into Lox, e.g. to run a predicate
+    Callback(fn(Vec<Value>, &Interpreter) -> Result<Value>),
+}
+
 #[derive(Clone)]
 pub struct NativeFunction {
     name: String,
     arity: usize,
-    func: fn(Vec<Value>) -> Result<Value>,
+    /// How many trailing arguments may be left out (the body sees a shorter `Vec`)
+    optional: usize,
+    func: NativeBody,
+}
+
+impl NativeFunction {
+    pub fn new(name: &str, arity: usize, func: Rc<dyn Fn(Vec<Value>) -> Result<Value>>) -> Self {
+        Self { name: name.to_string(), arity, optional: 0, func: NativeBody::Plain(func) }
+    }
 }
 
 impl Callable for NativeFunction {
@@ -39,7 +56,11 @@ impl Callable for NativeFunction {
     }
 
     fn call(&self, arguments: Vec<Value>, interpreter: Option<RefCell<Interpreter>>) -> Result<Value> {
-        (self.func)(arguments)
+        match (&self.func, interpreter) {
+            (NativeBody::Plain(func), _) => func(arguments),
+            (NativeBody::Callback(func), Some(interpreter)) => func(arguments, &interpreter.borrow()),
+            (NativeBody::Callback(_), None) => Err(native_error(&self.name, "Interpreter not available.")),
+        }
     }
 
     fn name(&self) -> &str {
@@ -50,7 +71,10 @@ impl Callable for NativeFunction {
 #[derive(Clone, Debug)]
 pub struct LoxClass {
     pub name: String,
+    pub superclass: Option<Rc<LoxClass>>,
     pub methods: HashMap<String, LoxFunction>,
+    /// Static methods, called on the class itself
+    pub class_methods: HashMap<String, LoxFunction>,
 }
 
 impl PartialEq for LoxClass {
@@ -60,21 +84,55 @@ impl PartialEq for LoxClass {
 }
 
 impl LoxClass{
-    pub fn new(name: String, methods: HashMap<String, LoxFunction>) -> Self{
-        Self { name, methods }
+    pub fn new(name: String, superclass: Option<Rc<LoxClass>>, methods: HashMap<String, LoxFunction>,
+        class_methods: HashMap<String, LoxFunction>) -> Self{
+        Self { name, superclass, methods, class_methods }
     }
 
+    /// Look up a method on this class, then walk up the superclass chain
     pub fn find_method(&self, name: &str) -> Option<&LoxFunction> {
-        self.methods.get(name)
+        if let Some(method) = self.methods.get(name) {
+            return Some(method);
+        }
+        self.superclass.as_ref().and_then(|superclass| superclass.find_method(name))
+    }
+
+    /// Look up a static method on this class, then walk up the superclass chain
+    pub fn find_class_method(&self, name: &str) -> Option<&LoxFunction> {
+        if let Some(method) = self.class_methods.get(name) {
+            return Some(method);
+        }
+        self.superclass.as_ref().and_then(|superclass| superclass.find_class_method(name))
+    }
+
+    /// The `init` method, inherited or not, as a callable
+    fn initializer(&self) -> Option<LoxCallable> {
+        self.find_method("init").map(|init| LoxCallable::LoxFunction(init.clone()))
+    }
+
+    /// Whether constructing with `count` arguments is allowed; without an
+    /// `init` method the class takes none
+    pub fn accepts(&self, count: usize) -> bool {
+        self.initializer().map_or(count == 0, |init| init.accepts(count))
+    }
+
+    /// Describe the accepted argument count, like `LoxCallable::arity_description`
+    pub fn arity_description(&self) -> String {
+        self.initializer().map_or("0".to_string(), |init| init.arity_description())
     }
 }
 impl Callable for LoxClass {
     fn arity(&self) -> usize {
-        0
+        self.initializer().map_or(0, |init| init.arity())
     }
-    fn call(&self, _arguments: Vec<Value>, _interpreter: Option<RefCell<Interpreter>>) -> Result<Value> {
-        let instance = LoxInstance::new(Rc::new(self.clone()));
-        Ok(Value::Instance(Rc::new(RefCell::new(instance))))
+    fn call(&self, arguments: Vec<Value>, interpreter: Option<RefCell<Interpreter>>) -> Result<Value> {
+        let instance = Rc::new(RefCell::new(LoxInstance::new(Rc::new(self.clone()))));
+        if let Some(init) = self.find_method("init")
+            && let Value::Callable(init) = init.bind(Rc::clone(&instance))
+        {
+            init.call(arguments, interpreter)?;
+        }
+        Ok(Value::Instance(instance))
     }
     fn name(&self) -> &str {
         &self.name
@@ -85,11 +143,13 @@ impl Callable for LoxClass {
 pub struct LoxInstance {
     pub class: Rc<LoxClass>,
     pub fields: HashMap<String, Value>,
+    /// Set while the class's `on_set` observer runs, so its own sets don't re-trigger it
+    pub in_on_set: bool,
 }
 
 impl LoxInstance {
     pub fn new(class: Rc<LoxClass>) -> Self{
-        Self { class, fields: HashMap::new() }
+        Self { class, fields: HashMap::new(), in_on_set: false }
     }
 
     pub fn class(&self) -> &LoxClass {
@@ -100,18 +160,19 @@ impl LoxInstance {
         format!("{} instance", self.class.name)
     }
 
-    pub fn get(&self, name: &str) -> Result<Value> {
-        if self.fields.contains_key(name) {
-            return Ok(self.fields.get(name).unwrap().clone());
+    /// Read a field, or bind a method to this exact instance (not a copy)
+    pub fn get(instance: &Rc<RefCell<LoxInstance>>, name: &crate::token::Token) -> Result<Value> {
+        if let Some(value) = instance.borrow().fields.get(&name.lexeme) {
+            return Ok(value.clone());
         }
-        let method = self.class.find_method(name);
-        if let Some(method) = method {
-            return Ok(method.bind(Rc::new(RefCell::new(self.clone()))))
+        let class = Rc::clone(&instance.borrow().class);
+        if let Some(method) = class.find_method(&name.lexeme) {
+            return Ok(method.bind(Rc::clone(instance)))
         }
 
         return Err(Error::RuntimeError(RuntimeError::new(
-            crate::token::Token::new(crate::token::TokenType::IDENTIFIER, name.to_string(), 0, None),
-            format!("Undefined property '{}'.", name),
+            name.clone(),
+            format!("Undefined property '{}'.", name.lexeme),
         )));
     }
 
@@ -122,9 +183,8 @@ impl LoxInstance {
 
 impl PartialEq for LoxInstance {
     fn eq(&self, other: &Self) -> bool {
-        // より厳密には、同じインスタンス（参照の同一性）を確認すべきですが、
-        // ここでは簡易的に同じクラスかどうかで判定します
-        Rc::ptr_eq(&self.class, &other.class)
+        // インスタンスは参照の同一性で比較する
+        std::ptr::eq(self, other)
     }
 }
 
@@ -132,20 +192,167 @@ impl PartialEq for LoxInstance {
 pub struct LoxFunction {
     name: String,
     params: Vec<String>,
-    body: Vec<Stmt>,
+    /// Shared, since the function value is cloned on every lookup
+    body: Rc<Vec<Stmt>>,
     closure: Rc<Environment>,
     is_initializer: bool,
+    /// Body contains `yield`, so calling it returns a generator
+    is_generator: bool,
+    /// Getter method: called as soon as the property is read
+    pub is_getter: bool,
+    /// Default values of the trailing parameters, evaluated in `closure` when left out
+    pub defaults: Rc<Vec<Expr>>,
+    /// The last parameter is a rest parameter bound to a list of the surplus arguments
+    pub is_variadic: bool,
 }
 impl LoxFunction {
     /// 新しいLoxFunctionを作成する
     pub fn new(name: String, params: Vec<String>, body: Vec<Stmt>, closure: Rc<Environment>, is_initializer: bool) -> Self {
-        Self { name, params, body, closure, is_initializer }
+        let is_generator = contains_yield(&body);
+        Self { name, params, body: Rc::new(body), closure, is_initializer, is_generator, is_getter: false, defaults: Rc::new(Vec::new()), is_variadic: false }
+    }
+
+    /// Run a generator body to completion, collecting every yielded value.
+    /// Generators are eager: the whole body runs when the function is called,
+    /// and `next()` then hands the collected values out one at a time.
+    fn run_generator(&self, interpreter: &RefCell<Interpreter>, env: Rc<Environment>) -> Result<Value> {
+        let previous = interpreter.borrow_mut().yielded.replace(Vec::new());
+        let res = interpreter.borrow_mut().execute_block(&self.body, env);
+        let values = std::mem::replace(&mut interpreter.borrow_mut().yielded, previous).unwrap_or_default();
+        match res {
+            // return でジェネレータを途中で終了できる
+            Ok(_) | Err(Error::ReturnError(_)) => {
+                Ok(Value::Generator(Rc::new(RefCell::new(LoxGenerator::new(values)))))
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// The instance a method was bound to, i.e. `this` in its closure
+    fn bound_this(&self) -> Result<Value> {
+        let this_token = crate::token::Token::new(crate::token::TokenType::THIS, "this".to_string(), 0, 0, None);
+        self.closure.get(&this_token)
+    }
+
+    /// Number of parameters bound to a single argument (all but a rest parameter)
+    fn fixed_arity(&self) -> usize {
+        self.params.len() - self.is_variadic as usize
+    }
+
+    /// Number of arguments a call must pass; the rest have defaults
+    pub fn min_arity(&self) -> usize {
+        self.fixed_arity() - self.defaults.len()
+    }
+
+    /// Append the defaults of the parameters missing from `arguments`
+    fn fill_defaults(&self, arguments: &mut Vec<Value>, interpreter: &RefCell<Interpreter>) -> Result<()> {
+        let first_default = self.min_arity();
+        // 呼び出し側が引数の数を確かめていない場合 (ネイティブからの呼び出しなど)
+        if arguments.len() < first_default {
+            return Err(native_error(&self.name, &format!(
+                "Expected {} arguments but got {}.",
+                LoxCallable::LoxFunction(self.clone()).arity_description(),
+                arguments.len()
+            )));
+        }
+        let mut interpreter = interpreter.borrow_mut();
+        let previous = std::mem::replace(&mut interpreter.environment, Rc::clone(&self.closure));
+        let mut result = Ok(());
+        for default in &self.defaults[arguments.len() - first_default..] {
+            match interpreter.evaluate(default) {
+                Ok(value) => arguments.push(value),
+                Err(err) => {
+                    result = Err(err);
+                    break;
+                }
+            }
+        }
+        interpreter.environment = previous;
+        result
+    }
+
+    /// Bind a static method: `this` refers to the class itself
+    pub fn bind_class(&self, class: LoxClass) -> Value {
+        let env = Rc::new(Environment::new(Some(Rc::clone(&self.closure))));
+        env.define("this".to_string(), Value::Class(class));
+        Value::Callable(LoxCallable::LoxFunction(LoxFunction { closure: env, is_initializer: false, ..self.clone() }))
     }
 
     pub fn bind(&self, instance: Rc<RefCell<LoxInstance>>) -> Value {
         let env = Rc::new(Environment::new(Some(Rc::clone(&self.closure))));
         env.define("this".to_string(), Value::Instance(Rc::clone(&instance)));
-        Value::Callable(LoxCallable::LoxFunction(LoxFunction::new(self.name.clone(), self.params.clone(), self.body.clone(), env, self.is_initializer)))
+        Value::Callable(LoxCallable::LoxFunction(LoxFunction { closure: env, ..self.clone() }))
+    }
+}
+
+/// Helper: does this body yield? Nested function declarations don't count,
+/// since a yield there makes the inner function the generator.
+fn contains_yield(statements: &[Stmt]) -> bool {
+    statements.iter().any(|stmt| match stmt {
+        Stmt::YieldStmt(_) => true,
+        Stmt::Block(block) => contains_yield(&block.statements),
+        Stmt::IfStatement(if_stmt) => {
+            contains_yield(std::slice::from_ref(&*if_stmt.then_branch))
+                || if_stmt.else_branch.as_deref().is_some_and(|stmt| contains_yield(std::slice::from_ref(stmt)))
+        }
+        Stmt::WhileStmt(while_stmt) => contains_yield(std::slice::from_ref(&*while_stmt.body)),
+        Stmt::DoWhileStmt(do_while_stmt) => contains_yield(std::slice::from_ref(&*do_while_stmt.body)),
+        Stmt::ForStmt(for_stmt) => contains_yield(std::slice::from_ref(&*for_stmt.body)),
+        Stmt::SwitchStmt(switch_stmt) => {
+            switch_stmt.cases.iter().any(|(_, body)| contains_yield(body))
+                || switch_stmt.default.as_deref().is_some_and(contains_yield)
+        }
+        _ => false,
+    })
+}
+
+/// Enum declared with `enum Name { ... }`
+#[derive(Debug)]
+pub struct LoxEnum {
+    pub name: String,
+    pub variants: Vec<Rc<EnumVariant>>,
+}
+
+impl LoxEnum {
+    pub fn new(name: &str, variants: &[&str]) -> Self {
+        let variants = variants
+            .iter()
+            .map(|variant| Rc::new(EnumVariant { enum_name: name.to_string(), name: variant.to_string() }))
+            .collect();
+        Self { name: name.to_string(), variants }
+    }
+
+    pub fn find_variant(&self, name: &str) -> Option<Rc<EnumVariant>> {
+        self.variants.iter().find(|variant| variant.name == name).cloned()
+    }
+}
+
+/// A single enum variant such as `Color.Red`
+#[derive(Debug)]
+pub struct EnumVariant {
+    pub enum_name: String,
+    pub name: String,
+}
+
+/// Values collected from a generator call, handed out in order by `next()`
+#[derive(Clone, Debug)]
+pub struct LoxGenerator {
+    values: Vec<Value>,
+    position: usize,
+}
+
+impl LoxGenerator {
+    pub fn new(values: Vec<Value>) -> Self {
+        Self { values, position: 0 }
+    }
+
+    /// The next value, or `None` once every value has been returned
+    pub fn next(&mut self) -> Option<Value> {
+        let value = self.values.get(self.position).cloned();
+        if value.is_some() {
+            self.position += 1;
+        }
+        value
     }
 }
 
@@ -154,23 +361,35 @@ impl Callable for LoxFunction {
         self.params.len()
     }
 
-    fn call(&self, arguments: Vec<Value>, interpreter: Option<RefCell<Interpreter>>) -> Result<Value> {
+    fn call(&self, mut arguments: Vec<Value>, interpreter: Option<RefCell<Interpreter>>) -> Result<Value> {
         if let Some(interpreter) = interpreter {
+            let name = crate::token::Token::new(crate::token::TokenType::IDENTIFIER, self.name.clone(), 0, 0, None);
+            interpreter.borrow_mut().enter_call(&name)?;
+            if arguments.len() < self.fixed_arity() {
+                self.fill_defaults(&mut arguments, &interpreter)?;
+            }
+            if self.is_variadic {
+                let rest = arguments.split_off(self.fixed_arity());
+                arguments.push(Value::List(Rc::new(RefCell::new(rest))));
+            }
             // 関数内部用の環境を作成（クロージャを親として使う）
             let env = Rc::new(Environment::new(Some(Rc::clone(&self.closure))));
             for (param, arg) in self.params.iter().zip(arguments.into_iter()) {
                 env.define(param.clone(), arg);
             }
+            if self.is_generator {
+                return self.run_generator(&interpreter, env);
+            }
             let res = interpreter.borrow_mut().execute_block(&self.body, env);
             match res {
+                // イニシャライザは最後まで実行しても `return;` で抜けても this を返す
+                Ok(_) if self.is_initializer => self.bound_this(),
                 Ok(_) => Ok(Value::Nil),
                 Err(err) => match err {
                     Error::ReturnError(return_err) => {
                         if let Some(value) = return_err.value {
                             if self.is_initializer {
-                                // イニシャライザの場合、常にthisを返す
-                                let this = self.closure.get("this")?;
-                                return Ok(this);
+                                return self.bound_this();
                             }
                             return Ok(value);
                         } else {
@@ -182,7 +401,7 @@ impl Callable for LoxFunction {
             }
         } else {
             Err(Error::RuntimeError(RuntimeError::new(
-                crate::token::Token::new(crate::token::TokenType::IDENTIFIER, self.name.clone(), 0, None),
+                crate::token::Token::new(crate::token::TokenType::IDENTIFIER, self.name.clone(), 0, 0, None),
                 "Interpreter is required to call LoxFunction.".to_string(),
             )))
         }
@@ -203,6 +422,32 @@ impl LoxCallable {
         }
     }
 
+    /// Whether a call with `count` arguments is allowed
+    pub fn accepts(&self, count: usize) -> bool {
+        match self {
+            LoxCallable::NativeFunction(native) => native.arity - native.optional <= count && count <= native.arity,
+            LoxCallable::LoxFunction(lox_func) => {
+                lox_func.min_arity() <= count && (lox_func.is_variadic || count <= lox_func.arity())
+            }
+        }
+    }
+
+    /// Describe the accepted argument count for error messages, e.g. "2", "0 to 1" or "1 or more"
+    pub fn arity_description(&self) -> String {
+        match self {
+            LoxCallable::LoxFunction(lox_func) if lox_func.is_variadic => {
+                format!("{} or more", lox_func.min_arity())
+            }
+            LoxCallable::NativeFunction(native) if native.optional > 0 => {
+                format!("{} to {}", native.arity - native.optional, native.arity)
+            }
+            LoxCallable::LoxFunction(lox_func) if lox_func.min_arity() < lox_func.arity() => {
+                format!("{} to {}", lox_func.min_arity(), lox_func.arity())
+            }
+            _ => self.arity().to_string(),
+        }
+    }
+
     /// 関数を呼び出す
     pub fn call(&self, arguments: Vec<Value>, interpreter: Option<RefCell<Interpreter>>) -> Result<Value> {
         match self {
@@ -247,6 +492,14 @@ impl PartialEq for LoxCallable {
 
 /// Native function implementations
 
+/// Helper: Build the runtime error raised by a native function
+fn native_error(name: &str, message: &str) -> Error {
+    Error::RuntimeError(RuntimeError::new(
+        crate::token::Token::new(crate::token::TokenType::IDENTIFIER, name.to_string(), 0, 0, None),
+        message.to_string(),
+    ))
+}
+
 /// clock() - Returns the current time in seconds since UNIX epoch
 pub fn native_clock(_args: Vec<Value>) -> Result<Value> {
     let duration = SystemTime::now()
@@ -260,6 +513,1291 @@ pub fn create_clock_function() -> LoxCallable {
     LoxCallable::NativeFunction(NativeFunction {
         name: "clock".to_string(),
         arity: 0,
-        func: native_clock,
+        optional: 0,
+        func: NativeBody::Plain(Rc::new(native_clock)),
+    })
+}
+
+/// weak(instance) - Returns a weak reference to an instance (breaks `Rc` cycles)
+pub fn native_weak(args: Vec<Value>) -> Result<Value> {
+    match &args[0] {
+        Value::Instance(instance) => Ok(Value::Weak(Rc::downgrade(instance))),
+        _ => Err(native_error("weak", "Argument to 'weak' must be an instance.")),
+    }
+}
+
+/// deref(weak) - Returns the referenced instance, or nil if it has been collected
+pub fn native_deref(args: Vec<Value>) -> Result<Value> {
+    match &args[0] {
+        Value::Weak(weak) => Ok(weak.upgrade().map_or(Value::Nil, Value::Instance)),
+        _ => Err(native_error("deref", "Argument to 'deref' must be a weak reference.")),
+    }
+}
+
+/// Helper function to create the weak native function
+pub fn create_weak_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "weak".to_string(),
+        arity: 1,
+        optional: 0,
+        func: NativeBody::Plain(Rc::new(native_weak)),
+    })
+}
+
+/// Helper function to create the deref native function
+pub fn create_deref_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "deref".to_string(),
+        arity: 1,
+        optional: 0,
+        func: NativeBody::Plain(Rc::new(native_deref)),
+    })
+}
+
+/// encode_url(s) - Percent-encodes every byte of `s` except unreserved characters
+pub fn native_encode_url(args: Vec<Value>) -> Result<Value> {
+    let s = match &args[0] {
+        Value::String(s) => s,
+        _ => return Err(native_error("encode_url", "Argument to 'encode_url' must be a string.")),
+    };
+    let mut encoded = String::new();
+    for byte in s.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    Ok(Value::String(encoded))
+}
+
+/// decode_url(s) - Reverses `encode_url`, failing on malformed `%` sequences
+pub fn native_decode_url(args: Vec<Value>) -> Result<Value> {
+    let s = match &args[0] {
+        Value::String(s) => s,
+        _ => return Err(native_error("decode_url", "Argument to 'decode_url' must be a string.")),
+    };
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let byte = bytes
+                .get(i + 1..i + 3)
+                .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .ok_or_else(|| native_error("decode_url", "Malformed percent-encoding."))?;
+            decoded.push(byte);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded)
+        .map(Value::String)
+        .map_err(|_| native_error("decode_url", "Decoded bytes are not valid UTF-8."))
+}
+
+/// Helper function to create the encode_url native function
+pub fn create_encode_url_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "encode_url".to_string(),
+        arity: 1,
+        optional: 0,
+        func: NativeBody::Plain(Rc::new(native_encode_url)),
+    })
+}
+
+/// Helper function to create the decode_url native function
+pub fn create_decode_url_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "decode_url".to_string(),
+        arity: 1,
+        optional: 0,
+        func: NativeBody::Plain(Rc::new(native_decode_url)),
+    })
+}
+
+/// name_of(fn) - Returns the declared name of a callable or class
+pub fn native_name_of(args: Vec<Value>) -> Result<Value> {
+    let name = match &args[0] {
+        Value::Callable(callable) => callable.name().to_string(),
+        Value::Class(class) => class.name.clone(),
+        _ => return Err(native_error("name_of", "Argument to 'name_of' must be a function or class.")),
+    };
+    if name.is_empty() {
+        return Ok(Value::String("<anonymous>".to_string()));
+    }
+    Ok(Value::String(name))
+}
+
+/// Helper function to create the name_of native function
+pub fn create_name_of_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "name_of".to_string(),
+        arity: 1,
+        optional: 0,
+        func: NativeBody::Plain(Rc::new(native_name_of)),
+    })
+}
+
+/// name(fn) - Like `name_of`, but only for functions
+pub fn native_name(args: Vec<Value>) -> Result<Value> {
+    match &args[0] {
+        Value::Callable(_) => native_name_of(args),
+        _ => Err(native_error("name", "Argument to 'name' must be a function.")),
+    }
+}
+
+/// Helper function to create the name native function
+pub fn create_name_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "name".to_string(),
+        arity: 1,
+        optional: 0,
+        func: NativeBody::Plain(Rc::new(native_name)),
+    })
+}
+
+/// arity(fn) - Returns the number of parameters a function declares, counting
+/// ones with defaults and a rest parameter
+pub fn native_arity(args: Vec<Value>) -> Result<Value> {
+    match &args[0] {
+        Value::Callable(callable) => Ok(Value::Number(callable.arity() as f64)),
+        Value::Class(class) => Ok(Value::Number(class.arity() as f64)),
+        _ => Err(native_error("arity", "Argument to 'arity' must be a function or class.")),
+    }
+}
+
+/// Helper function to create the arity native function
+pub fn create_arity_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "arity".to_string(),
+        arity: 1,
+        optional: 0,
+        func: NativeBody::Plain(Rc::new(native_arity)),
+    })
+}
+
+/// assert_type(value, name) - Returns `value` if its type is `name`, else raises a runtime error
+pub fn native_assert_type(args: Vec<Value>) -> Result<Value> {
+    let expected = match &args[1] {
+        Value::String(name) if Value::TYPE_NAMES.contains(&name.as_str()) => name,
+        Value::String(name) => return Err(native_error("assert_type", &format!("Unknown type name '{}'.", name))),
+        _ => return Err(native_error("assert_type", "Second argument to 'assert_type' must be a type name string.")),
+    };
+    let actual = args[0].type_name();
+    if actual != expected {
+        return Err(native_error("assert_type", &format!("Expected {}, got {}.", expected, actual)));
+    }
+    Ok(args[0].clone())
+}
+
+/// Helper function to create the assert_type native function
+pub fn create_assert_type_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "assert_type".to_string(),
+        arity: 2,
+        optional: 0,
+        func: NativeBody::Plain(Rc::new(native_assert_type)),
+    })
+}
+
+/// type(value) - Returns the name of the value's type, e.g. "number" or "instance"
+pub fn native_type(args: Vec<Value>) -> Result<Value> {
+    Ok(Value::String(args[0].type_name().to_string()))
+}
+
+/// Helper function to create the type native function
+pub fn create_type_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "type".to_string(),
+        arity: 1,
+        optional: 0,
+        func: NativeBody::Plain(Rc::new(native_type)),
+    })
+}
+
+/// Helper: read a non-negative integer argument, or raise a runtime error
+fn non_negative_integer(name: &str, value: &Value) -> Result<u64> {
+    match value {
+        Value::Number(n) if *n > MAX_EXACT_INTEGER => Err(native_error(name, &format!("Argument to '{}' must be at most 2^53.", name))),
+        Value::Number(n) if *n >= 0.0 && n.fract() == 0.0 => Ok(*n as u64),
+        _ => Err(native_error(name, &format!("Argument to '{}' must be a non-negative integer.", name))),
+    }
+}
+
+/// Largest integer a Lox number can hold exactly (2^53)
+const MAX_EXACT_INTEGER: f64 = 9007199254740992.0;
+
+/// Helper: primality by trial division
+fn is_prime(n: u64) -> bool {
+    if n < 2 {
+        return false;
+    }
+    let mut i = 2;
+    while i * i <= n {
+        if n % i == 0 {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+/// is_prime(n) - Returns true if the non-negative integer `n` is prime
+pub fn native_is_prime(args: Vec<Value>) -> Result<Value> {
+    let n = non_negative_integer("is_prime", &args[0])?;
+    Ok(Value::Bool(is_prime(n)))
+}
+
+/// Helper function to create the is_prime native function
+pub fn create_is_prime_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "is_prime".to_string(),
+        arity: 1,
+        optional: 0,
+        func: NativeBody::Plain(Rc::new(native_is_prime)),
+    })
+}
+
+/// next_prime(n) - Returns the smallest prime strictly greater than `n`
+pub fn native_next_prime(args: Vec<Value>) -> Result<Value> {
+    let mut n = non_negative_integer("next_prime", &args[0])?;
+    loop {
+        n = n.checked_add(1).ok_or_else(|| native_error("next_prime", "Argument to 'next_prime' is too large."))?;
+        if is_prime(n) {
+            return Ok(Value::Number(n as f64));
+        }
+    }
+}
+
+/// Helper function to create the next_prime native function
+pub fn create_next_prime_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "next_prime".to_string(),
+        arity: 1,
+        optional: 0,
+        func: NativeBody::Plain(Rc::new(native_next_prime)),
+    })
+}
+
+/// Helper: read a number argument, or raise a runtime error
+fn number_arg(name: &str, value: &Value) -> Result<f64> {
+    match value {
+        Value::Number(n) => Ok(*n),
+        _ => Err(native_error(name, &format!("Argument to '{}' must be a number.", name))),
+    }
+}
+
+/// Helper: read a string argument, or raise a runtime error
+fn string_arg<'a>(name: &str, value: &'a Value) -> Result<&'a str> {
+    match value {
+        Value::String(s) => Ok(s),
+        _ => Err(native_error(name, &format!("Argument to '{}' must be a string.", name))),
+    }
+}
+
+/// Helper: build a native that applies `f` to a single number
+fn math_function(name: &str, func: fn(f64) -> f64) -> LoxCallable {
+    let native_name = name.to_string();
+    LoxCallable::NativeFunction(NativeFunction {
+        name: name.to_string(),
+        arity: 1,
+        optional: 0,
+        func: NativeBody::Plain(Rc::new(move |args: Vec<Value>| Ok(Value::Number(func(number_arg(&native_name, &args[0])?))))),
+    })
+}
+
+/// sqrt(n), floor(n), ceil(n), abs(n) - Number helpers
+pub fn create_sqrt_function() -> LoxCallable {
+    math_function("sqrt", f64::sqrt)
+}
+
+pub fn create_floor_function() -> LoxCallable {
+    math_function("floor", f64::floor)
+}
+
+pub fn create_ceil_function() -> LoxCallable {
+    math_function("ceil", f64::ceil)
+}
+
+pub fn create_abs_function() -> LoxCallable {
+    math_function("abs", f64::abs)
+}
+
+/// pow(base, exp) - Returns `base` raised to `exp`
+pub fn native_pow(args: Vec<Value>) -> Result<Value> {
+    let base = number_arg("pow", &args[0])?;
+    let exp = number_arg("pow", &args[1])?;
+    Ok(Value::Number(base.powf(exp)))
+}
+
+/// Helper function to create the pow native function
+pub fn create_pow_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "pow".to_string(),
+        arity: 2,
+        optional: 0,
+        func: NativeBody::Plain(Rc::new(native_pow)),
+    })
+}
+
+/// len(x) - Returns the number of characters in a string or elements in a list
+pub fn native_len(args: Vec<Value>) -> Result<Value> {
+    match &args[0] {
+        Value::String(s) => Ok(Value::Number(s.chars().count() as f64)),
+        Value::List(list) => Ok(Value::Number(list.borrow().len() as f64)),
+        _ => Err(native_error("len", "Argument to 'len' must be a string or list.")),
+    }
+}
+
+/// Helper function to create the len native function
+pub fn create_len_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "len".to_string(),
+        arity: 1,
+        optional: 0,
+        func: NativeBody::Plain(Rc::new(native_len)),
+    })
+}
+
+/// is_empty(x) - Whether a string, list or map has no elements
+pub fn native_is_empty(args: Vec<Value>) -> Result<Value> {
+    match &args[0] {
+        Value::String(s) => Ok(Value::Bool(s.is_empty())),
+        Value::List(list) => Ok(Value::Bool(list.borrow().is_empty())),
+        Value::Map(map) => Ok(Value::Bool(map.borrow().is_empty())),
+        _ => Err(native_error("is_empty", "Argument to 'is_empty' must be a string, list or map.")),
+    }
+}
+
+/// Helper function to create the is_empty native function
+pub fn create_is_empty_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "is_empty".to_string(),
+        arity: 1,
+        optional: 0,
+        func: NativeBody::Plain(Rc::new(native_is_empty)),
+    })
+}
+
+/// substring(s, start, end) - Returns the characters of `s` in `[start, end)`
+pub fn native_substring(args: Vec<Value>) -> Result<Value> {
+    let s = string_arg("substring", &args[0])?;
+    let start = non_negative_integer("substring", &args[1])? as usize;
+    let end = non_negative_integer("substring", &args[2])? as usize;
+    let length = s.chars().count();
+    if start > end || end > length {
+        return Err(native_error(
+            "substring",
+            &format!("Substring range {}..{} out of bounds for length {}.", start, end, length),
+        ));
+    }
+    Ok(Value::String(s.chars().skip(start).take(end - start).collect()))
+}
+
+/// Helper function to create the substring native function
+pub fn create_substring_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "substring".to_string(),
+        arity: 3,
+        optional: 0,
+        func: NativeBody::Plain(Rc::new(native_substring)),
+    })
+}
+
+/// charAt(s, i) - Returns the character at index `i` as a one-character string.
+/// Indexes count characters, not bytes, so "é" is a single character.
+pub fn native_char_at(args: Vec<Value>) -> Result<Value> {
+    let s = string_arg("charAt", &args[0])?;
+    let index = number_arg("charAt", &args[1])?;
+    let length = s.chars().count();
+    let found = if index >= 0.0 && index.fract() == 0.0 { s.chars().nth(index as usize) } else { None };
+    match found {
+        Some(c) => Ok(Value::String(c.to_string())),
+        None => Err(native_error(
+            "charAt",
+            &format!("Index {} out of range for string of length {}.", format_number(index), length),
+        )),
+    }
+}
+
+/// Helper function to create the charAt native function
+pub fn create_char_at_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "charAt".to_string(),
+        arity: 2,
+        optional: 0,
+        func: NativeBody::Plain(Rc::new(native_char_at)),
+    })
+}
+
+/// chr(n) - Returns the one-character string for Unicode code point `n`
+pub fn native_chr(args: Vec<Value>) -> Result<Value> {
+    let code = non_negative_integer("chr", &args[0])?;
+    match u32::try_from(code).ok().and_then(char::from_u32) {
+        Some(c) => Ok(Value::String(c.to_string())),
+        None => Err(native_error("chr", &format!("Invalid code point {}.", code))),
+    }
+}
+
+/// Helper function to create the chr native function
+pub fn create_chr_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "chr".to_string(),
+        arity: 1,
+        optional: 0,
+        func: NativeBody::Plain(Rc::new(native_chr)),
+    })
+}
+
+/// ord(s) - Returns the Unicode code point of a one-character string
+pub fn native_ord(args: Vec<Value>) -> Result<Value> {
+    let s = string_arg("ord", &args[0])?;
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(Value::Number(c as u32 as f64)),
+        _ => Err(native_error("ord", "Argument to 'ord' must be a single character.")),
+    }
+}
+
+/// Helper function to create the ord native function
+pub fn create_ord_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "ord".to_string(),
+        arity: 1,
+        optional: 0,
+        func: NativeBody::Plain(Rc::new(native_ord)),
+    })
+}
+
+/// Most digits `format_number` will print after the decimal point
+const MAX_DECIMALS: usize = 100;
+
+/// format_number(x, decimals, group) - Formats `x` with a fixed number of
+/// decimals, optionally inserting thousands separators
+pub fn native_format_number(args: Vec<Value>) -> Result<Value> {
+    let x = number_arg("format_number", &args[0])?;
+    let decimals = non_negative_integer("format_number", &args[1])? as usize;
+    if decimals > MAX_DECIMALS {
+        return Err(native_error("format_number", &format!("Decimals for 'format_number' must be at most {}.", MAX_DECIMALS)));
+    }
+    let group = match &args[2] {
+        Value::Bool(b) => *b,
+        _ => return Err(native_error("format_number", "Third argument to 'format_number' must be a bool.")),
+    };
+
+    let text = format!("{:.*}", decimals, x);
+    if !group {
+        return Ok(Value::String(text));
+    }
+    let (sign, digits) = match text.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", text.as_str()),
+    };
+    let (integer, fraction) = match digits.find('.') {
+        Some(dot) => digits.split_at(dot),
+        None => (digits, ""),
+    };
+    let mut grouped = String::new();
+    for (i, c) in integer.chars().enumerate() {
+        if i > 0 && (integer.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(c);
+    }
+    Ok(Value::String(format!("{}{}{}", sign, grouped, fraction)))
+}
+
+/// Helper function to create the format_number native function
+pub fn create_format_number_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "format_number".to_string(),
+        arity: 3,
+        optional: 0,
+        func: NativeBody::Plain(Rc::new(native_format_number)),
+    })
+}
+
+/// next(gen) - Returns the generator's next value, or nil once it is exhausted
+pub fn native_next(args: Vec<Value>) -> Result<Value> {
+    match &args[0] {
+        Value::Generator(generator) => Ok(generator.borrow_mut().next().unwrap_or(Value::Nil)),
+        _ => Err(native_error("next", "Argument to 'next' must be a generator.")),
+    }
+}
+
+/// Helper function to create the next native function
+pub fn create_next_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "next".to_string(),
+        arity: 1,
+        optional: 0,
+        func: NativeBody::Plain(Rc::new(native_next)),
+    })
+}
+
+/// Helper: read the (string, width, fill) arguments shared by the pad natives
+fn pad_args<'a>(name: &str, args: &'a [Value]) -> Result<(&'a str, usize, char)> {
+    let s = string_arg(name, &args[0])?;
+    let width = non_negative_integer(name, &args[1])? as usize;
+    let mut fill = string_arg(name, &args[2])?.chars();
+    match (fill.next(), fill.next()) {
+        (Some(c), None) => Ok((s, width, c)),
+        _ => Err(native_error(name, &format!("Fill for '{}' must be a single character.", name))),
+    }
+}
+
+/// Helper: pad `s` with `left` and `right` copies of `fill`, or raise a
+/// runtime error if the result can't be allocated
+fn pad(name: &str, s: &str, fill: char, left: usize, right: usize) -> Result<Value> {
+    let mut padded = String::new();
+    left.checked_add(right)
+        .and_then(|extra| extra.checked_mul(fill.len_utf8()))
+        .and_then(|bytes| bytes.checked_add(s.len()))
+        .and_then(|bytes| padded.try_reserve_exact(bytes).ok())
+        .ok_or_else(|| native_error(name, &format!("Can't allocate a string of {} characters.", left + right + s.chars().count())))?;
+    padded.extend(std::iter::repeat_n(fill, left));
+    padded.push_str(s);
+    padded.extend(std::iter::repeat_n(fill, right));
+    Ok(Value::String(padded))
+}
+
+/// pad_start(s, width, fill) - Right-aligns `s` in `width` characters
+pub fn native_pad_start(args: Vec<Value>) -> Result<Value> {
+    let (s, width, fill) = pad_args("pad_start", &args)?;
+    let extra = width.saturating_sub(s.chars().count());
+    pad("pad_start", s, fill, extra, 0)
+}
+
+/// Helper function to create the pad_start native function
+pub fn create_pad_start_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "pad_start".to_string(),
+        arity: 3,
+        optional: 0,
+        func: NativeBody::Plain(Rc::new(native_pad_start)),
+    })
+}
+
+/// pad_end(s, width, fill) - Left-aligns `s` in `width` characters
+pub fn native_pad_end(args: Vec<Value>) -> Result<Value> {
+    let (s, width, fill) = pad_args("pad_end", &args)?;
+    let extra = width.saturating_sub(s.chars().count());
+    pad("pad_end", s, fill, 0, extra)
+}
+
+/// Helper function to create the pad_end native function
+pub fn create_pad_end_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "pad_end".to_string(),
+        arity: 3,
+        optional: 0,
+        func: NativeBody::Plain(Rc::new(native_pad_end)),
+    })
+}
+
+/// pad_center(s, width, fill) - Centers `s` in `width` characters. When the
+/// padding can't be split evenly, the extra character goes on the right.
+pub fn native_pad_center(args: Vec<Value>) -> Result<Value> {
+    let (s, width, fill) = pad_args("pad_center", &args)?;
+    let extra = width.saturating_sub(s.chars().count());
+    pad("pad_center", s, fill, extra / 2, extra - extra / 2)
+}
+
+/// Helper function to create the pad_center native function
+pub fn create_pad_center_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "pad_center".to_string(),
+        arity: 3,
+        optional: 0,
+        func: NativeBody::Plain(Rc::new(native_pad_center)),
+    })
+}
+
+/// Helper: read a list argument, or raise a runtime error
+fn list_arg(name: &str, value: &Value) -> Result<Rc<RefCell<Vec<Value>>>> {
+    match value {
+        Value::List(list) => Ok(Rc::clone(list)),
+        _ => Err(native_error(name, &format!("Argument to '{}' must be a list.", name))),
+    }
+}
+
+/// push(list, value) - Appends `value` to the end of `list`
+pub fn native_push(args: Vec<Value>) -> Result<Value> {
+    let list = list_arg("push", &args[0])?;
+    list.borrow_mut().push(args[1].clone());
+    Ok(Value::Nil)
+}
+
+/// Helper function to create the push native function
+pub fn create_push_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "push".to_string(),
+        arity: 2,
+        optional: 0,
+        func: NativeBody::Plain(Rc::new(native_push)),
+    })
+}
+
+/// pop(list) - Removes and returns the last element of `list`
+pub fn native_pop(args: Vec<Value>) -> Result<Value> {
+    let list = list_arg("pop", &args[0])?;
+    let value = list.borrow_mut().pop();
+    value.ok_or_else(|| native_error("pop", "Can't pop from an empty list."))
+}
+
+/// Helper function to create the pop native function
+pub fn create_pop_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "pop".to_string(),
+        arity: 1,
+        optional: 0,
+        func: NativeBody::Plain(Rc::new(native_pop)),
+    })
+}
+
+/// Helper: render a value for `diff` messages, quoting strings
+fn diff_repr(value: Option<&Value>) -> String {
+    match value {
+        Some(Value::String(s)) => format!("\"{}\"", s),
+        Some(value) => value.to_string(),
+        None => "<missing>".to_string(),
+    }
+}
+
+/// Helper: walk two values in parallel and describe the first difference.
+/// `in_progress` holds the pairs of lists, maps and instances being walked;
+/// meeting one again means a cycle, which has no difference of its own.
+fn first_difference(path: &str, expected: Option<&Value>, actual: Option<&Value>, in_progress: &mut Vec<(usize, usize)>) -> Option<String> {
+    match (expected, actual) {
+        (Some(Value::List(a)), Some(Value::List(b))) => {
+            let pair = (Rc::as_ptr(a) as usize, Rc::as_ptr(b) as usize);
+            if Rc::ptr_eq(a, b) || in_progress.contains(&pair) {
+                return None;
+            }
+            let (a, b) = (a.borrow(), b.borrow());
+            in_progress.push(pair);
+            let difference = (0..a.len().max(b.len())).find_map(|i| {
+                first_difference(&format!("{}[{}]", path, i), a.get(i), b.get(i), in_progress)
+            });
+            in_progress.pop();
+            difference
+        }
+        (Some(Value::Map(a)), Some(Value::Map(b))) => {
+            let pair = (Rc::as_ptr(a) as usize, Rc::as_ptr(b) as usize);
+            if Rc::ptr_eq(a, b) || in_progress.contains(&pair) {
+                return None;
+            }
+            let (a, b) = (a.borrow(), b.borrow());
+            let mut keys: Vec<&String> = a.keys().chain(b.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            in_progress.push(pair);
+            let difference = keys.into_iter().find_map(|key| {
+                first_difference(&format!("{}[\"{}\"]", path, key), a.get(key), b.get(key), in_progress)
+            });
+            in_progress.pop();
+            difference
+        }
+        (Some(Value::Instance(a)), Some(Value::Instance(b))) => {
+            let pair = (Rc::as_ptr(a) as usize, Rc::as_ptr(b) as usize);
+            if Rc::ptr_eq(a, b) || in_progress.contains(&pair) {
+                return None;
+            }
+            let (a, b) = (a.borrow(), b.borrow());
+            if a.class.name != b.class.name {
+                return Some(format!("at {}: expected {} instance got {} instance", path, a.class.name, b.class.name));
+            }
+            let mut names: Vec<&String> = a.fields.keys().chain(b.fields.keys()).collect();
+            names.sort();
+            names.dedup();
+            in_progress.push(pair);
+            let difference = names.into_iter().find_map(|name| {
+                first_difference(&format!("{}.{}", path, name), a.fields.get(name), b.fields.get(name), in_progress)
+            });
+            in_progress.pop();
+            difference
+        }
+        _ if expected == actual => None,
+        _ if path.is_empty() => Some(format!("expected {} got {}", diff_repr(expected), diff_repr(actual))),
+        _ => Some(format!("at {}: expected {} got {}", path, diff_repr(expected), diff_repr(actual))),
+    }
+}
+
+/// diff(a, b) - Describes the first difference between two values, walking
+/// into lists, maps and instance fields; returns "" when they are equal
+pub fn native_diff(args: Vec<Value>) -> Result<Value> {
+    Ok(Value::String(first_difference("", Some(&args[0]), Some(&args[1]), &mut Vec::new()).unwrap_or_default()))
+}
+
+/// Helper function to create the diff native function
+pub fn create_diff_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "diff".to_string(),
+        arity: 2,
+        optional: 0,
+        func: NativeBody::Plain(Rc::new(native_diff)),
+    })
+}
+
+/// mod_positive(a, b) - Euclidean modulo: always in `0..|b|`, even for negative `a`
+pub fn native_mod_positive(args: Vec<Value>) -> Result<Value> {
+    let a = number_arg("mod_positive", &args[0])?;
+    let b = number_arg("mod_positive", &args[1])?;
+    if b == 0.0 {
+        return Err(native_error("mod_positive", "Modulo by zero."));
+    }
+    // -0 にならないよう 0.0 を足す
+    Ok(Value::Number(a.rem_euclid(b) + 0.0))
+}
+
+/// Helper function to create the mod_positive native function
+pub fn create_mod_positive_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "mod_positive".to_string(),
+        arity: 2,
+        optional: 0,
+        func: NativeBody::Plain(Rc::new(native_mod_positive)),
+    })
+}
+
+/// sum_range(lo, hi) - Sum of the integers from `lo` to `hi` inclusive, in O(1).
+/// A reversed range (`lo > hi`) is empty and sums to 0.
+pub fn native_sum_range(args: Vec<Value>) -> Result<Value> {
+    let lo = number_arg("sum_range", &args[0])?;
+    let hi = number_arg("sum_range", &args[1])?;
+    if lo.fract() != 0.0 || hi.fract() != 0.0 {
+        return Err(native_error("sum_range", "Arguments to 'sum_range' must be integers."));
+    }
+    if lo > hi {
+        return Ok(Value::Number(0.0));
+    }
+    // 等差数列の和: (初項 + 末項) * 項数 / 2
+    Ok(Value::Number((lo + hi) * (hi - lo + 1.0) / 2.0))
+}
+
+/// Helper function to create the sum_range native function
+pub fn create_sum_range_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "sum_range".to_string(),
+        arity: 2,
+        optional: 0,
+        func: NativeBody::Plain(Rc::new(native_sum_range)),
+    })
+}
+
+/// Helper: call a Lox function from inside a native, checking it accepts
+/// `arguments.len()` arguments by the same rules as a direct call
+fn call_back(name: &str, interpreter: &Interpreter, callee: &Value, arguments: Vec<Value>) -> Result<Value> {
+    match callee {
+        Value::Callable(callable) if callable.accepts(arguments.len()) => {
+            callable.call(arguments, Some(RefCell::new(interpreter.clone())))
+        }
+        Value::Callable(callable) => Err(native_error(
+            name,
+            &format!("Function passed to '{}' must take {} arguments but takes {}.", name, arguments.len(), callable.arity_description()),
+        )),
+        _ => Err(native_error(name, &format!("Argument to '{}' must be a function.", name))),
+    }
+}
+
+/// partition(list, fn) - Splits `list` into `[matching, non_matching]` by a predicate
+pub fn native_partition(args: Vec<Value>, interpreter: &Interpreter) -> Result<Value> {
+    let list = list_arg("partition", &args[0])?;
+    if !matches!(args[1], Value::Callable(_)) {
+        return Err(native_error("partition", "Second argument to 'partition' must be a function."));
+    }
+    let elements = list.borrow().clone();
+    let (mut matching, mut rest) = (Vec::new(), Vec::new());
+    for element in elements {
+        let keep = call_back("partition", interpreter, &args[1], vec![element.clone()])?;
+        if interpreter.is_truthy(&keep) {
+            matching.push(element);
+        } else {
+            rest.push(element);
+        }
+    }
+    let bucket = |values| Value::List(Rc::new(RefCell::new(values)));
+    Ok(bucket(vec![bucket(matching), bucket(rest)]))
+}
+
+/// Helper function to create the partition native function
+pub fn create_partition_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "partition".to_string(),
+        arity: 2,
+        optional: 0,
+        func: NativeBody::Callback(native_partition),
+    })
+}
+
+/// Helper: an empty list with room for the `n` elements of `list_of`/`list_from`
+fn list_with_capacity(name: &str, n: u64) -> Result<Vec<Value>> {
+    let mut elements = Vec::new();
+    elements
+        .try_reserve_exact(n as usize)
+        .map_err(|_| native_error(name, &format!("Can't allocate a list of {} elements.", n)))?;
+    Ok(elements)
+}
+
+/// list_of(n, value) - A new list of length `n` with every element set to `value`.
+/// Lists and maps are shared, not copied: `list_of(2, [])` holds one list twice.
+pub fn native_list_of(args: Vec<Value>) -> Result<Value> {
+    let n = non_negative_integer("list_of", &args[0])?;
+    let mut elements = list_with_capacity("list_of", n)?;
+    elements.resize(n as usize, args[1].clone());
+    Ok(Value::List(Rc::new(RefCell::new(elements))))
+}
+
+/// Helper function to create the list_of native function
+pub fn create_list_of_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "list_of".to_string(),
+        arity: 2,
+        optional: 0,
+        func: NativeBody::Plain(Rc::new(native_list_of)),
+    })
+}
+
+/// list_from(n, fn) - A new list of length `n` whose element `i` is `fn(i)`
+pub fn native_list_from(args: Vec<Value>, interpreter: &Interpreter) -> Result<Value> {
+    let n = non_negative_integer("list_from", &args[0])?;
+    if !matches!(args[1], Value::Callable(_)) {
+        return Err(native_error("list_from", "Second argument to 'list_from' must be a function."));
+    }
+    let mut elements = list_with_capacity("list_from", n)?;
+    for i in 0..n {
+        elements.push(call_back("list_from", interpreter, &args[1], vec![Value::Number(i as f64)])?);
+    }
+    Ok(Value::List(Rc::new(RefCell::new(elements))))
+}
+
+/// Helper function to create the list_from native function
+pub fn create_list_from_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "list_from".to_string(),
+        arity: 2,
+        optional: 0,
+        func: NativeBody::Callback(native_list_from),
+    })
+}
+
+/// take(list, n) - A new list of the first `n` elements of `list` (all of them if it is shorter)
+pub fn native_take(args: Vec<Value>) -> Result<Value> {
+    let list = list_arg("take", &args[0])?;
+    let n = non_negative_integer("take", &args[1])?;
+    let elements = list.borrow();
+    let end = (n as usize).min(elements.len());
+    Ok(Value::List(Rc::new(RefCell::new(elements[..end].to_vec()))))
+}
+
+/// Helper function to create the take native function
+pub fn create_take_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "take".to_string(),
+        arity: 2,
+        optional: 0,
+        func: NativeBody::Plain(Rc::new(native_take)),
+    })
+}
+
+/// drop(list, n) - A new list without the first `n` elements of `list` (empty if it is shorter)
+pub fn native_drop(args: Vec<Value>) -> Result<Value> {
+    let list = list_arg("drop", &args[0])?;
+    let n = non_negative_integer("drop", &args[1])?;
+    let elements = list.borrow();
+    let start = (n as usize).min(elements.len());
+    Ok(Value::List(Rc::new(RefCell::new(elements[start..].to_vec()))))
+}
+
+/// Helper function to create the drop native function
+pub fn create_drop_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "drop".to_string(),
+        arity: 2,
+        optional: 0,
+        func: NativeBody::Plain(Rc::new(native_drop)),
+    })
+}
+
+/// Helper: a copy of `list` and how many of its leading elements satisfy the predicate `fn`
+fn leading_matches(name: &str, args: &[Value], interpreter: &Interpreter) -> Result<(Vec<Value>, usize)> {
+    let list = list_arg(name, &args[0])?;
+    if !matches!(args[1], Value::Callable(_)) {
+        return Err(native_error(name, &format!("Second argument to '{}' must be a function.", name)));
+    }
+    let elements = list.borrow().clone();
+    let mut count = 0;
+    for element in &elements {
+        let keep = call_back(name, interpreter, &args[1], vec![element.clone()])?;
+        if !interpreter.is_truthy(&keep) {
+            break;
+        }
+        count += 1;
+    }
+    Ok((elements, count))
+}
+
+/// take_while(list, fn) - A new list of the leading elements for which `fn` is truthy
+pub fn native_take_while(args: Vec<Value>, interpreter: &Interpreter) -> Result<Value> {
+    let (mut elements, count) = leading_matches("take_while", &args, interpreter)?;
+    elements.truncate(count);
+    Ok(Value::List(Rc::new(RefCell::new(elements))))
+}
+
+/// Helper function to create the take_while native function
+pub fn create_take_while_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "take_while".to_string(),
+        arity: 2,
+        optional: 0,
+        func: NativeBody::Callback(native_take_while),
+    })
+}
+
+/// drop_while(list, fn) - A new list starting at the first element for which `fn` is falsey
+pub fn native_drop_while(args: Vec<Value>, interpreter: &Interpreter) -> Result<Value> {
+    let (mut elements, count) = leading_matches("drop_while", &args, interpreter)?;
+    elements.drain(..count);
+    Ok(Value::List(Rc::new(RefCell::new(elements))))
+}
+
+/// Helper function to create the drop_while native function
+pub fn create_drop_while_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "drop_while".to_string(),
+        arity: 2,
+        optional: 0,
+        func: NativeBody::Callback(native_drop_while),
+    })
+}
+
+/// count(list, value) - How many elements of `list` are `==` to `value`
+pub fn native_count(args: Vec<Value>) -> Result<Value> {
+    let list = list_arg("count", &args[0])?;
+    let elements = list.borrow().clone();
+    let count = elements.iter().filter(|element| **element == args[1]).count();
+    Ok(Value::Number(count as f64))
+}
+
+/// Helper function to create the count native function
+pub fn create_count_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "count".to_string(),
+        arity: 2,
+        optional: 0,
+        func: NativeBody::Plain(Rc::new(native_count)),
+    })
+}
+
+/// count_where(list, fn) - How many elements of `list` make the predicate `fn` truthy
+pub fn native_count_where(args: Vec<Value>, interpreter: &Interpreter) -> Result<Value> {
+    let list = list_arg("count_where", &args[0])?;
+    if !matches!(args[1], Value::Callable(_)) {
+        return Err(native_error("count_where", "Second argument to 'count_where' must be a function."));
+    }
+    let elements = list.borrow().clone();
+    let mut count = 0;
+    for element in elements {
+        let matched = call_back("count_where", interpreter, &args[1], vec![element])?;
+        if interpreter.is_truthy(&matched) {
+            count += 1;
+        }
+    }
+    Ok(Value::Number(count as f64))
+}
+
+/// Helper function to create the count_where native function
+pub fn create_count_where_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "count_where".to_string(),
+        arity: 2,
+        optional: 0,
+        func: NativeBody::Callback(native_count_where),
+    })
+}
+
+/// Helper: shared body of `min_by`/`max_by`. Keeps the first element whose key
+/// beats the current best according to `better`.
+fn extreme_by(name: &str, args: &[Value], interpreter: &Interpreter, better: std::cmp::Ordering) -> Result<Value> {
+    let list = list_arg(name, &args[0])?;
+    let elements = list.borrow().clone();
+    if elements.is_empty() {
+        return Err(native_error(name, &format!("Can't call '{}' on an empty list.", name)));
+    }
+    let mut best: Option<(Value, Value)> = None;
+    for element in elements {
+        let key = call_back(name, interpreter, &args[1], vec![element.clone()])?;
+        let replace = match (&best, &key) {
+            (None, Value::Number(_) | Value::String(_)) => true,
+            (Some((Value::Number(a), _)), Value::Number(b)) => b.partial_cmp(a) == Some(better),
+            (Some((Value::String(a), _)), Value::String(b)) => b.cmp(a) == better,
+            _ => return Err(native_error(name, &format!("Keys for '{}' must be all numbers or all strings.", name))),
+        };
+        if replace {
+            best = Some((key, element));
+        }
+    }
+    Ok(best.map(|(_, element)| element).unwrap_or(Value::Nil))
+}
+
+/// min_by(list, key_fn) - Returns the element with the smallest key
+pub fn native_min_by(args: Vec<Value>, interpreter: &Interpreter) -> Result<Value> {
+    extreme_by("min_by", &args, interpreter, std::cmp::Ordering::Less)
+}
+
+/// Helper function to create the min_by native function
+pub fn create_min_by_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "min_by".to_string(),
+        arity: 2,
+        optional: 0,
+        func: NativeBody::Callback(native_min_by),
+    })
+}
+
+/// max_by(list, key_fn) - Returns the element with the largest key
+pub fn native_max_by(args: Vec<Value>, interpreter: &Interpreter) -> Result<Value> {
+    extreme_by("max_by", &args, interpreter, std::cmp::Ordering::Greater)
+}
+
+/// Helper function to create the max_by native function
+pub fn create_max_by_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "max_by".to_string(),
+        arity: 2,
+        optional: 0,
+        func: NativeBody::Callback(native_max_by),
+    })
+}
+
+/// input([prompt]) - Reads one line from stdin without its newline; nil at end of input
+pub fn native_input(args: Vec<Value>) -> Result<Value> {
+    use std::io::{self, Write};
+    if let Some(prompt) = args.first() {
+        print!("{}", prompt);
+        io::stdout().flush().map_err(|e| native_error("input", &e.to_string()))?;
+    }
+    let mut line = String::new();
+    match io::stdin().read_line(&mut line) {
+        Ok(0) => Ok(Value::Nil),
+        Ok(_) => {
+            let trimmed = line.trim_end_matches(['\n', '\r']).len();
+            line.truncate(trimmed);
+            Ok(Value::String(line))
+        }
+        Err(e) => Err(native_error("input", &e.to_string())),
+    }
+}
+
+/// Helper function to create the input native function
+pub fn create_input_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "input".to_string(),
+        arity: 1,
+        optional: 1,
+        func: NativeBody::Plain(Rc::new(native_input)),
+    })
+}
+
+/// exit(code) - Flushes the output and ends the process with `code`. This skips the
+/// usual exit codes for errors (65/70) entirely; see `Interpreter::set_exit_handler`.
+pub fn native_exit(args: Vec<Value>, interpreter: &Interpreter) -> Result<Value> {
+    let code = number_arg("exit", &args[0])?;
+    interpreter.flush_output().map_err(|e| native_error("exit", &e.to_string()))?;
+    interpreter.exit(code as i32);
+    Ok(Value::Nil)
+}
+
+/// Helper function to create the exit native function
+pub fn create_exit_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "exit".to_string(),
+        arity: 1,
+        optional: 0,
+        func: NativeBody::Callback(native_exit),
+    })
+}
+
+/// str(value) - Converts a value to the string `print` would show, calling `toString` on instances
+pub fn native_str(args: Vec<Value>, interpreter: &Interpreter) -> Result<Value> {
+    Ok(Value::String(interpreter.clone().stringify_mut(&args[0])?))
+}
+
+/// Helper function to create the str native function
+pub fn create_str_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "str".to_string(),
+        arity: 1,
+        optional: 0,
+        func: NativeBody::Callback(native_str),
+    })
+}
+
+/// num(string) - Parses a number, ignoring surrounding whitespace. Returns nil if
+/// the string is not a finite number (so "nan" and "inf" are rejected too).
+pub fn native_num(args: Vec<Value>) -> Result<Value> {
+    let text = string_arg("num", &args[0])?;
+    Ok(match text.trim().parse::<f64>() {
+        Ok(n) if n.is_finite() => Value::Number(n),
+        _ => Value::Nil,
+    })
+}
+
+/// Helper function to create the num native function
+pub fn create_num_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "num".to_string(),
+        arity: 1,
+        optional: 0,
+        func: NativeBody::Plain(Rc::new(native_num)),
+    })
+}
+
+/// bool(value) - Applies Lox truthiness: only nil and false are false
+pub fn native_bool(args: Vec<Value>, interpreter: &Interpreter) -> Result<Value> {
+    Ok(Value::Bool(interpreter.is_truthy(&args[0])))
+}
+
+/// Helper function to create the bool native function
+pub fn create_bool_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "bool".to_string(),
+        arity: 1,
+        optional: 0,
+        func: NativeBody::Callback(native_bool),
+    })
+}
+
+/// Helper: read a map argument, or raise a runtime error
+fn map_arg(name: &str, value: &Value) -> Result<Rc<RefCell<HashMap<String, Value>>>> {
+    match value {
+        Value::Map(map) => Ok(Rc::clone(map)),
+        _ => Err(native_error(name, &format!("Argument to '{}' must be a map.", name))),
+    }
+}
+
+/// keys(map) - Returns the keys of `map` as a list, in sorted order
+pub fn native_keys(args: Vec<Value>) -> Result<Value> {
+    let map = map_arg("keys", &args[0])?;
+    let mut keys: Vec<String> = map.borrow().keys().cloned().collect();
+    keys.sort();
+    Ok(Value::List(Rc::new(RefCell::new(keys.into_iter().map(Value::String).collect()))))
+}
+
+/// Helper function to create the keys native function
+pub fn create_keys_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "keys".to_string(),
+        arity: 1,
+        optional: 0,
+        func: NativeBody::Plain(Rc::new(native_keys)),
+    })
+}
+
+/// has(map, key) - Whether `map` has an entry for `key`
+pub fn native_has(args: Vec<Value>) -> Result<Value> {
+    let map = map_arg("has", &args[0])?;
+    let key = string_arg("has", &args[1])?;
+    Ok(Value::Bool(map.borrow().contains_key(key)))
+}
+
+/// Helper function to create the has native function
+pub fn create_has_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "has".to_string(),
+        arity: 2,
+        optional: 0,
+        func: NativeBody::Plain(Rc::new(native_has)),
+    })
+}
+
+/// get_or(map, key, default) - The value stored under `key`, or `default` if
+/// `map` has no such entry. A stored nil is returned as nil.
+pub fn native_get_or(args: Vec<Value>) -> Result<Value> {
+    let map = map_arg("get_or", &args[0])?;
+    let key = string_arg("get_or", &args[1])?;
+    Ok(map.borrow().get(key).cloned().unwrap_or_else(|| args[2].clone()))
+}
+
+/// Helper function to create the get_or native function
+pub fn create_get_or_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "get_or".to_string(),
+        arity: 3,
+        optional: 0,
+        func: NativeBody::Plain(Rc::new(native_get_or)),
+    })
+}
+
+/// remove(map, key) - Deletes the entry for `key` and returns its value, or nil if there was none
+pub fn native_remove(args: Vec<Value>) -> Result<Value> {
+    let map = map_arg("remove", &args[0])?;
+    let key = string_arg("remove", &args[1])?;
+    let removed = map.borrow_mut().remove(key);
+    Ok(removed.unwrap_or(Value::Nil))
+}
+
+/// Helper function to create the remove native function
+pub fn create_remove_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "remove".to_string(),
+        arity: 2,
+        optional: 0,
+        func: NativeBody::Plain(Rc::new(native_remove)),
+    })
+}
+
+/// clear(map) - Removes every entry from `map` in place
+pub fn native_clear(args: Vec<Value>) -> Result<Value> {
+    let map = map_arg("clear", &args[0])?;
+    map.borrow_mut().clear();
+    Ok(Value::Nil)
+}
+
+/// Helper function to create the clear native function
+pub fn create_clear_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "clear".to_string(),
+        arity: 1,
+        optional: 0,
+        func: NativeBody::Plain(Rc::new(native_clear)),
+    })
+}
+
+/// size(map) - The number of entries in `map`
+pub fn native_size(args: Vec<Value>) -> Result<Value> {
+    let map = map_arg("size", &args[0])?;
+    let size = map.borrow().len();
+    Ok(Value::Number(size as f64))
+}
+
+/// Helper function to create the size native function
+pub fn create_size_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "size".to_string(),
+        arity: 1,
+        optional: 0,
+        func: NativeBody::Plain(Rc::new(native_size)),
+    })
+}
+
+/// eprint(value) - Writes `value` and a newline to the interpreter's error sink (stderr by default)
+pub fn native_eprint(args: Vec<Value>, interpreter: &Interpreter) -> Result<Value> {
+    let text = format!("{}\n", interpreter.clone().stringify_mut(&args[0])?);
+    interpreter.write_error(&text).map_err(|e| native_error("eprint", &e.to_string()))?;
+    Ok(Value::Nil)
+}
+
+/// Helper function to create the eprint native function
+pub fn create_eprint_function() -> LoxCallable {
+    LoxCallable::NativeFunction(NativeFunction {
+        name: "eprint".to_string(),
+        arity: 1,
+        optional: 0,
+        func: NativeBody::Callback(native_eprint),
     })
 }
@@ -1,72 +1,123 @@
 use std::collections::HashMap;
 use std::rc::Rc;
 use std::cell::RefCell;
-use crate::token::{LiteralType, Token, TokenType};
+use crate::token::Token;
 use crate::error::{RuntimeError, Result, Error};
 use crate::token::Value;
 
 #[derive(Debug, Clone)]
 pub struct Environment{
-    pub values: RefCell<HashMap<String, Value>>,
+    /// Variables in the order they were defined. The resolver numbers locals
+    /// the same way, so resolved accesses index this directly.
+    slots: RefCell<Vec<(String, Value)>>,
+    /// Slot of each name, for lookups by name (globals and unresolved names)
+    names: RefCell<HashMap<String, usize>>,
     pub enclosing: Option<Rc<Environment>>,
 }
 
 impl Environment {
     pub fn new(enclosing: Option<Rc<Environment>>) -> Self {
         Self {
-            values: RefCell::new(HashMap::new()),
+            slots: RefCell::new(Vec::new()),
+            names: RefCell::new(HashMap::new()),
             enclosing,
         }
     }
 
     pub fn define(&self, name: String, value: Value){
-        self.values.borrow_mut().insert(name, value);
+        let mut slots = self.slots.borrow_mut();
+        let mut names = self.names.borrow_mut();
+        match names.get(&name) {
+            Some(&slot) => slots[slot].1 = value,
+            None => {
+                names.insert(name.clone(), slots.len());
+                slots.push((name, value));
+            }
+        }
+    }
+
+    /// Remove a binding from this environment (not its ancestors)
+    pub fn remove(&self, name: &str) {
+        let mut names = self.names.borrow_mut();
+        if let Some(removed) = names.remove(name) {
+            self.slots.borrow_mut().remove(removed);
+            for slot in names.values_mut() {
+                if *slot > removed {
+                    *slot -= 1;
+                }
+            }
+        }
+    }
+
+    /// Every binding defined directly in this environment, in definition order
+    pub fn bindings(&self) -> Vec<(String, Value)> {
+        self.slots.borrow().clone()
     }
 
-    pub fn put(&self, name: &str, value: Value) -> Result<()>{
-        if self.values.borrow().contains_key(name){
-            self.values.borrow_mut().insert(name.to_string(), value);
+    /// Assign to an existing variable. `name` is the token from the assignment,
+    /// so an undefined-variable error points at the right line.
+    pub fn put(&self, name: &Token, value: Value) -> Result<()>{
+        if let Some(&slot) = self.names.borrow().get(&name.lexeme) {
+            self.slots.borrow_mut()[slot].1 = value;
             return Ok(());
         }
         if let Some(enclosing) = &self.enclosing {
             return enclosing.put(name, value);
         }
         return Err(Error::RuntimeError(RuntimeError::new(
-            Token::new(TokenType::IDENTIFIER, name.to_string(), 0, None),
-            format!("Undefined variable '{}'.", name),
+            name.clone(),
+            format!("Undefined variable '{}'.", name.lexeme),
         )));
     }
 
-    pub fn get(&self, name: &str) -> Result<Value>{
-        if let Some(value) = self.values.borrow().get(name) {
-            return Ok(value.clone());
+    pub fn get(&self, name: &Token) -> Result<Value>{
+        if let Some(&slot) = self.names.borrow().get(&name.lexeme) {
+            return Ok(self.slots.borrow()[slot].1.clone());
         }
         if let Some(enclosing) = &self.enclosing {
             return enclosing.get(name);
         }
         Err(Error::RuntimeError(RuntimeError::new(
-            Token::new(TokenType::IDENTIFIER, name.to_string(), 0, None),
-            format!("Undefined variable '{}'.", name),
+            name.clone(),
+            format!("Undefined variable '{}'.", name.lexeme),
         )))
     }
 
-    fn ancestor(&self, distance: usize) -> Rc<Environment> {
-        let mut environment = Rc::new(self.clone());
+    fn ancestor(&self, distance: usize) -> &Environment {
+        let mut environment = self;
         for _ in 0..distance {
             if let Some(enclosing) = &environment.enclosing {
-                environment = Rc::clone(enclosing);
+                environment = enclosing;
             }
         }
         environment
     }
 
-    pub fn assign_at(&self, distance: usize, name: &str, value: Value) -> Result<()> {
-        let environment = self.ancestor(distance);
-        environment.put(name, value)
+    /// Assign to a resolved variable: the one in `slot` of the environment
+    /// `distance` levels up. Only locals are resolved; globals go by name.
+    pub fn assign_at(&self, distance: usize, slot: usize, name: &Token, value: Value) -> Result<()> {
+        match self.ancestor(distance).slots.borrow_mut().get_mut(slot) {
+            Some(binding) if binding.0 == name.lexeme => {
+                binding.1 = value;
+                Ok(())
+            }
+            _ => Err(Self::unresolved(name)),
+        }
     }
 
-    pub fn get_at(&self, distance: usize, name: &str) -> Result<Value> {
-        let environment = self.ancestor(distance);
-        environment.get(name)
+    /// Read a resolved variable; see `assign_at`
+    pub fn get_at(&self, distance: usize, slot: usize, name: &Token) -> Result<Value> {
+        match self.ancestor(distance).slots.borrow().get(slot) {
+            Some(binding) if binding.0 == name.lexeme => Ok(binding.1.clone()),
+            _ => Err(Self::unresolved(name)),
+        }
     }
-}
\ No newline at end of file
+
+    /// The resolver and the environments disagree about where `name` lives
+    fn unresolved(name: &Token) -> Error {
+        Error::RuntimeError(RuntimeError::new(
+            name.clone(),
+            format!("Internal error: '{}' is not in its resolved slot.", name.lexeme),
+        ))
+    }
+}
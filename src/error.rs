@@ -4,6 +4,8 @@ use std::fmt;
 pub enum Error {
     RuntimeError(RuntimeError),
     ReturnError(ReturnError),
+    /// Scan or parse errors from `Interpreter::eval_source`, one formatted message each
+    SyntaxError(Vec<String>),
 }
 
 /// Runtime error type
@@ -31,7 +33,68 @@ impl RuntimeError {
 
 impl fmt::Display for RuntimeError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "[line {}] Runtime Error: {}", self.token.line, self.message)
+        write!(f, "[line {}:{}] Runtime Error: {}", self.token.line, self.token.column, self.message)
+    }
+}
+
+/// Error found by the scanner, e.g. an unterminated string
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl ScanError {
+    pub fn new(line: usize, message: String) -> Self {
+        Self { line, message }
+    }
+}
+
+impl fmt::Display for ScanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[line {}] Error: {}", self.line, self.message)
+    }
+}
+
+/// Problem the resolver noticed that doesn't stop the program from running
+#[derive(Debug, Clone, PartialEq)]
+pub struct Warning {
+    pub line: usize,
+    pub message: String,
+}
+
+impl Warning {
+    pub fn new(line: usize, message: String) -> Self {
+        Self { line, message }
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[line {}] Warning: {}", self.line, self.message)
+    }
+}
+
+/// Syntax error reported by the parser
+#[derive(Debug, Clone)]
+pub struct ParseError {
+    pub token: Token,
+    pub message: String,
+}
+
+impl ParseError {
+    pub fn new(token: Token, message: String) -> Self {
+        Self { token, message }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.token.type_ == crate::token::TokenType::EOF {
+            write!(f, "[line {}] Error at end: {}", self.token.line, self.message)
+        } else {
+            write!(f, "[line {}] Error at '{}': {}", self.token.line, self.token.lexeme, self.message)
+        }
     }
 }
 
@@ -1,15 +1,19 @@
 use crate::token::{Token, LiteralType};
 use std::hash::{Hash, Hasher};
+use std::rc::Rc;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct VarDecl {
     pub name: Token,
     pub initializer: Option<Expr>,
+    /// Further names in `var a, b = list;`. When present, the initializer must
+    /// be a list whose elements go to `name` and then these, in order.
+    pub unpack: Vec<Token>,
 }
 
 impl VarDecl {
-    pub fn new(name: Token, initializer: Option<Expr>) -> Self {
-        Self { name, initializer }
+    pub fn new(name: Token, initializer: Option<Expr>, unpack: Vec<Token>) -> Self {
+        Self { name, initializer, unpack }
     }
 }
 
@@ -20,14 +24,22 @@ pub trait StmtVisitor<R> {
     fn visit_block_stmt(&mut self, block: &Block) -> R;
     fn visit_if_stmt(&mut self, if_stmt: &IfStatement) -> R;
     fn visit_while_stmt(&mut self, while_stmt: &WhileStmt) -> R;
+    fn visit_do_while_stmt(&mut self, do_while_stmt: &DoWhileStmt) -> R;
     fn visit_for_stmt(&mut self, for_stmt: &ForStmt) -> R;
     fn visit_break_stmt(&mut self, _break_stmt: &BreakStmt) -> R;
     fn visit_continue_stmt(&mut self, _continue_stmt: &ContinueStmt) -> R;
+    fn visit_breakpoint_stmt(&mut self, breakpoint_stmt: &BreakpointStmt) -> R;
     fn visit_function_stmt(&mut self, function_stmt: &FunctionStmt) -> R;
     fn visit_return_stmt(&mut self, return_stmt: &ReturnStmt) -> R;
+    fn visit_yield_stmt(&mut self, yield_stmt: &YieldStmt) -> R;
+    fn visit_incr_stmt(&mut self, incr_stmt: &IncrStmt) -> R;
     fn visit_class_decl(&mut self, class_decl: &ClassDecl) -> R;    
+    fn visit_enum_decl(&mut self, enum_decl: &EnumDecl) -> R;
+    fn visit_switch_stmt(&mut self, switch_stmt: &SwitchStmt) -> R;
+    fn visit_assert_stmt(&mut self, assert_stmt: &AssertStmt) -> R;
+    fn visit_defer_stmt(&mut self, defer_stmt: &DeferStmt) -> R;
 }
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Stmt {
     Print(Print),
     Expression(Expression),
@@ -35,12 +47,20 @@ pub enum Stmt {
     VarDeclaration(VarDecl),
     Block(Block),
     WhileStmt(WhileStmt),
+    DoWhileStmt(DoWhileStmt),
     ForStmt(ForStmt),
     BreakStmt(BreakStmt),
     ContinueStmt(ContinueStmt),
+    BreakpointStmt(BreakpointStmt),
     FunctionStmt(FunctionStmt),
     ReturnStmt(ReturnStmt),
+    YieldStmt(YieldStmt),
+    IncrStmt(IncrStmt),
     ClassDecl(ClassDecl),
+    EnumDecl(EnumDecl),
+    SwitchStmt(SwitchStmt),
+    AssertStmt(AssertStmt),
+    DeferStmt(DeferStmt),
 }
 
 impl Stmt {
@@ -52,27 +72,128 @@ impl Stmt {
             Stmt::Block(block) => visitor.visit_block_stmt(block),
             Stmt::IfStatement(if_stmt) => visitor.visit_if_stmt(if_stmt),
             Stmt::WhileStmt(while_stmt) => visitor.visit_while_stmt(while_stmt),
+            Stmt::DoWhileStmt(do_while_stmt) => visitor.visit_do_while_stmt(do_while_stmt),
             Stmt::ForStmt(for_stmt) => visitor.visit_for_stmt(for_stmt),
             Stmt::BreakStmt(break_stmt) => visitor.visit_break_stmt(break_stmt),
             Stmt::ContinueStmt(continue_stmt) => visitor.visit_continue_stmt(continue_stmt),
+            Stmt::BreakpointStmt(breakpoint_stmt) => visitor.visit_breakpoint_stmt(breakpoint_stmt),
             Stmt::FunctionStmt(function_stmt) => visitor.visit_function_stmt(function_stmt),
             Stmt::ReturnStmt(return_stmt) => visitor.visit_return_stmt(return_stmt),
+            Stmt::YieldStmt(yield_stmt) => visitor.visit_yield_stmt(yield_stmt),
+            Stmt::IncrStmt(incr_stmt) => visitor.visit_incr_stmt(incr_stmt),
             Stmt::ClassDecl(class_decl) => visitor.visit_class_decl(class_decl),
+            Stmt::EnumDecl(enum_decl) => visitor.visit_enum_decl(enum_decl),
+            Stmt::SwitchStmt(switch_stmt) => visitor.visit_switch_stmt(switch_stmt),
+            Stmt::AssertStmt(assert_stmt) => visitor.visit_assert_stmt(assert_stmt),
+            Stmt::DeferStmt(defer_stmt) => visitor.visit_defer_stmt(defer_stmt),
+        }
+    }
+    /// Line of the first token of the statement, if any token was kept in the tree
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            Stmt::Print(stmt) => Some(stmt.keyword.line),
+            Stmt::Expression(stmt) => stmt.expression.line(),
+            Stmt::VarDeclaration(var_decl) => Some(var_decl.name.line),
+            Stmt::Block(block) => block.statements.iter().find_map(Stmt::line),
+            Stmt::IfStatement(if_stmt) => if_stmt.condition.line(),
+            Stmt::WhileStmt(while_stmt) => match &while_stmt.label {
+                Some(label) => Some(label.line),
+                None => while_stmt.condition.line(),
+            },
+            Stmt::DoWhileStmt(do_while_stmt) => match &do_while_stmt.label {
+                Some(label) => Some(label.line),
+                None => do_while_stmt.body.line(),
+            },
+            Stmt::ForStmt(for_stmt) => match &for_stmt.label {
+                Some(label) => Some(label.line),
+                None => for_stmt.initializer.as_ref().and_then(|initializer| initializer.line())
+                    .or_else(|| for_stmt.condition.as_ref().and_then(|condition| condition.line()))
+                    .or_else(|| for_stmt.body.line()),
+            },
+            Stmt::BreakStmt(break_stmt) => Some(break_stmt.keyword.line),
+            Stmt::ContinueStmt(continue_stmt) => Some(continue_stmt.keyword.line),
+            Stmt::BreakpointStmt(breakpoint_stmt) => Some(breakpoint_stmt.keyword.line),
+            Stmt::FunctionStmt(function_stmt) => Some(function_stmt.name.line),
+            Stmt::ReturnStmt(return_stmt) => Some(return_stmt.keyword.line),
+            Stmt::YieldStmt(yield_stmt) => Some(yield_stmt.keyword.line),
+            Stmt::IncrStmt(incr_stmt) => Some(incr_stmt.keyword.line),
+            Stmt::ClassDecl(class_decl) => Some(class_decl.name.line),
+            Stmt::EnumDecl(enum_decl) => Some(enum_decl.name.line),
+            Stmt::SwitchStmt(switch_stmt) => Some(switch_stmt.keyword.line),
+            Stmt::AssertStmt(assert_stmt) => Some(assert_stmt.keyword.line),
+            Stmt::DeferStmt(defer_stmt) => Some(defer_stmt.keyword.line),
         }
     }
 }
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ClassDecl {
     pub name: Token,
+    pub superclass: Option<Variable>,
     pub methods: Vec<FunctionStmt>,
+    /// Static methods, declared with a leading `class` keyword
+    pub class_methods: Vec<FunctionStmt>,
 }
 impl ClassDecl {
-    pub fn new(name: Token, methods: Vec<FunctionStmt>) -> Self {
-        Self { name, methods }
+    pub fn new(name: Token, superclass: Option<Variable>, methods: Vec<FunctionStmt>, class_methods: Vec<FunctionStmt>) -> Self {
+        Self { name, superclass, methods, class_methods }
+    }
+}
+
+/// `enum Name { A, B, C }`
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnumDecl {
+    pub name: Token,
+    pub variants: Vec<Token>,
+}
+impl EnumDecl {
+    pub fn new(name: Token, variants: Vec<Token>) -> Self {
+        Self { name, variants }
+    }
+}
+
+/// `switch (x) { case 1: ... default: ... }`; only the first matching arm runs
+#[derive(Debug, Clone, PartialEq)]
+pub struct SwitchStmt {
+    pub keyword: Token,
+    pub discriminant: Box<Expr>,
+    /// Each `case` value with the statements of its arm
+    pub cases: Vec<(Expr, Vec<Stmt>)>,
+    pub default: Option<Vec<Stmt>>,
+}
+impl SwitchStmt {
+    pub fn new(keyword: Token, discriminant: Box<Expr>, cases: Vec<(Expr, Vec<Stmt>)>, default: Option<Vec<Stmt>>) -> Self {
+        Self { keyword, discriminant, cases, default }
     }
 }
 
-#[derive(Debug, Clone)]
+/// `assert cond;` or `assert cond, message;`: a runtime error if `cond` is falsey
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssertStmt {
+    pub keyword: Token,
+    pub condition: Box<Expr>,
+    pub message: Option<Box<Expr>>,
+}
+impl AssertStmt {
+    pub fn new(keyword: Token, condition: Box<Expr>, message: Option<Box<Expr>>) -> Self {
+        Self { keyword, condition, message }
+    }
+}
+
+/// `defer { ... }`: the block runs when the enclosing block exits, in reverse
+/// order of registration, even on `return` or a runtime error
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeferStmt {
+    pub keyword: Token,
+    /// Always a `Stmt::Block`; shared so registering it doesn't copy the tree
+    pub body: Rc<Stmt>,
+}
+impl DeferStmt {
+    pub fn new(keyword: Token, body: Rc<Stmt>) -> Self {
+        Self { keyword, body }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct ReturnStmt {
     pub keyword: Token,
     pub value: Option<Box<Expr>>,
@@ -83,56 +204,118 @@ impl ReturnStmt {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct YieldStmt {
+    pub keyword: Token,
+    pub value: Box<Expr>,
+}
+impl YieldStmt {
+    pub fn new(keyword: Token, value: Box<Expr>) -> Self {
+        Self { keyword, value }
+    }
+}
+
+/// `incr x;`, `decr x;` or `incr x by n;`; `keyword` says which direction
+#[derive(Debug, Clone, PartialEq)]
+pub struct IncrStmt {
+    pub keyword: Token,
+    pub target: Variable,
+    pub amount: Option<Box<Expr>>,
+}
+impl IncrStmt {
+    pub fn new(keyword: Token, target: Variable, amount: Option<Box<Expr>>) -> Self {
+        Self { keyword, target, amount }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct FunctionStmt {
     pub name: Token,
     pub params: Vec<Token>,
+    /// Default values of the trailing parameters, in order; `params.len() - defaults.len()` are required
+    pub defaults: Vec<Expr>,
+    /// The last parameter was declared as `...name` and collects the surplus arguments
+    pub is_variadic: bool,
     pub body: Box<Vec<Stmt>>,
+    /// Method declared without a parameter list; runs on property access
+    pub is_getter: bool,
 }
 impl FunctionStmt {
-    pub fn new(name: Token, params: Vec<Token>, body: Box<Vec<Stmt>>) -> Self {
-        Self { name, params, body }
+    pub fn new(name: Token, params: Vec<Token>, defaults: Vec<Expr>, is_variadic: bool, body: Box<Vec<Stmt>>, is_getter: bool) -> Self {
+        Self { name, params, defaults, is_variadic, body, is_getter }
     }
 }
-#[derive(Debug, Clone)]
-pub struct BreakStmt;
+#[derive(Debug, Clone, PartialEq)]
+pub struct BreakStmt {
+    pub keyword: Token,
+    /// `break name;` leaves the enclosing loop labeled `name`
+    pub label: Option<Token>,
+}
 impl BreakStmt {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(keyword: Token, label: Option<Token>) -> Self {
+        Self { keyword, label }
     }
 } 
-#[derive(Debug, Clone)]
-pub struct ContinueStmt;
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContinueStmt {
+    pub keyword: Token,
+    pub label: Option<Token>,
+}
 impl ContinueStmt {
-    pub fn new() -> Self {      
-        Self {}
+    pub fn new(keyword: Token, label: Option<Token>) -> Self {
+        Self { keyword, label }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct BreakpointStmt {
+    pub keyword: Token,
+}
+impl BreakpointStmt {
+    pub fn new(keyword: Token) -> Self {
+        Self { keyword }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct ForStmt {
     pub initializer: Option<Box<Stmt>>,
     pub condition: Option<Box<Expr>>,
     pub increment: Option<Box<Expr>>,
     pub body: Box<Stmt>,
+    /// `name:` before the loop, the target of `break name;`/`continue name;`
+    pub label: Option<Token>,
 }
 impl ForStmt {
-    pub fn new(initializer: Option<Box<Stmt>>, condition: Option<Box<Expr>>, increment: Option<Box<Expr>>, body: Box<Stmt>) -> Self {
-        Self { initializer, condition, increment, body }
+    pub fn new(initializer: Option<Box<Stmt>>, condition: Option<Box<Expr>>, increment: Option<Box<Expr>>, body: Box<Stmt>, label: Option<Token>) -> Self {
+        Self { initializer, condition, increment, body, label }
     }
 }
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct WhileStmt {
     pub condition: Box<Expr>,
     pub body: Box<Stmt>,
+    pub label: Option<Token>,
 }
 impl WhileStmt {
-    pub fn new(condition: Box<Expr>, body: Box<Stmt>) -> Self {
-        Self { condition, body }
+    pub fn new(condition: Box<Expr>, body: Box<Stmt>, label: Option<Token>) -> Self {
+        Self { condition, body, label }
+    }
+}
+/// `do body while (condition);`
+#[derive(Debug, Clone, PartialEq)]
+pub struct DoWhileStmt {
+    pub body: Box<Stmt>,
+    pub condition: Box<Expr>,
+    pub label: Option<Token>,
+}
+impl DoWhileStmt {
+    pub fn new(body: Box<Stmt>, condition: Box<Expr>, label: Option<Token>) -> Self {
+        Self { body, condition, label }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct IfStatement {
     pub condition: Box<Expr>,
     pub then_branch: Box<Stmt>,
@@ -145,7 +328,7 @@ impl IfStatement {
     
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Block {
     pub statements: Vec<Stmt>,
 }
@@ -155,17 +338,19 @@ impl Block {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Print {
-    pub expression: Box<Expr>,
+    pub keyword: Token,
+    /// `print a, b;` prints its values on one line, separated
+    pub expressions: Vec<Expr>,
 }
 
 impl Print {
-    pub fn new(expression: Box<Expr>) -> Self {
-        Self { expression }
+    pub fn new(keyword: Token, expressions: Vec<Expr>) -> Self {
+        Self { keyword, expressions }
     }
 }
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Expression {
     pub expression: Box<Expr>,
 }
@@ -189,6 +374,15 @@ pub trait ExprVisitor<R> {
     fn visit_get_expr(&mut self, expr: &Get) -> R;
     fn visit_set_expr(&mut self, expr: &Set) -> R;
     fn visit_this_expr(&mut self, expr: &This) -> R;
+    fn visit_super_expr(&mut self, expr: &Super) -> R;
+    fn visit_conditional_expr(&mut self, expr: &Conditional) -> R;
+    fn visit_lambda_expr(&mut self, expr: &Lambda) -> R;
+    fn visit_list_expr(&mut self, expr: &ListExpr) -> R;
+    fn visit_map_expr(&mut self, expr: &MapExpr) -> R;
+    fn visit_index_expr(&mut self, expr: &Index) -> R;
+    fn visit_index_set_expr(&mut self, expr: &IndexSet) -> R;
+    fn visit_interpolation_expr(&mut self, expr: &Interpolation) -> R;
+    fn visit_coalesce_expr(&mut self, expr: &Coalesce) -> R;
 }
 
 
@@ -204,9 +398,18 @@ pub enum Expr {
     Get(Get),
     Set(Set),
     This(This),
+    Super(Super),
     Call(Call),
     OR(OR),
     AND(AND),
+    Conditional(Conditional),
+    Lambda(Lambda),
+    List(ListExpr),
+    Map(MapExpr),
+    Index(Index),
+    IndexSet(IndexSet),
+    Interpolation(Interpolation),
+    Coalesce(Coalesce),
 }
 
 impl Eq for Expr {}
@@ -262,6 +465,42 @@ impl Hash for Expr {
                 11u8.hash(state);
                 e.hash(state);
             }
+            Expr::Super(e) => {
+                12u8.hash(state);
+                e.hash(state);
+            }
+            Expr::Conditional(e) => {
+                13u8.hash(state);
+                e.hash(state);
+            }
+            Expr::Lambda(e) => {
+                14u8.hash(state);
+                e.hash(state);
+            }
+            Expr::List(e) => {
+                15u8.hash(state);
+                e.hash(state);
+            }
+            Expr::Index(e) => {
+                16u8.hash(state);
+                e.hash(state);
+            }
+            Expr::IndexSet(e) => {
+                17u8.hash(state);
+                e.hash(state);
+            }
+            Expr::Interpolation(e) => {
+                18u8.hash(state);
+                e.hash(state);
+            }
+            Expr::Coalesce(e) => {
+                19u8.hash(state);
+                e.hash(state);
+            }
+            Expr::Map(e) => {
+                20u8.hash(state);
+                e.hash(state);
+            }
         }
     }
 }
@@ -280,7 +519,42 @@ impl Expr {
             Expr::Call(expr) => visitor.visit_call_expr(expr),
             Expr::Get(expr) => visitor.visit_get_expr(expr),
             Expr::Set(expr) => visitor.visit_set_expr(expr),
-            Expr::This(expr) => visitor.visit_this_expr(expr) 
+            Expr::This(expr) => visitor.visit_this_expr(expr),
+            Expr::Super(expr) => visitor.visit_super_expr(expr),
+            Expr::Conditional(expr) => visitor.visit_conditional_expr(expr),
+            Expr::Lambda(expr) => visitor.visit_lambda_expr(expr),
+            Expr::List(expr) => visitor.visit_list_expr(expr),
+            Expr::Map(expr) => visitor.visit_map_expr(expr),
+            Expr::Index(expr) => visitor.visit_index_expr(expr),
+            Expr::IndexSet(expr) => visitor.visit_index_set_expr(expr),
+            Expr::Interpolation(expr) => visitor.visit_interpolation_expr(expr),
+            Expr::Coalesce(expr) => visitor.visit_coalesce_expr(expr),
+        }
+    }
+    /// Line of the first token of the expression; literals keep no token
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            Expr::Binary(expr) => expr.left.line(),
+            Expr::Grouping(expr) => expr.expression.line(),
+            Expr::Literal(_) => None,
+            Expr::Unary(expr) => Some(expr.operator.line),
+            Expr::Variable(expr) => Some(expr.name.line),
+            Expr::Assignment(expr) => Some(expr.name.line),
+            Expr::OR(expr) => expr.left.line(),
+            Expr::AND(expr) => expr.left.line(),
+            Expr::Call(expr) => expr.callee.line(),
+            Expr::Get(expr) => expr.object.line(),
+            Expr::Set(expr) => expr.object.line(),
+            Expr::This(expr) => Some(expr.keyword.line),
+            Expr::Super(expr) => Some(expr.keyword.line),
+            Expr::Conditional(expr) => expr.condition.line(),
+            Expr::Lambda(expr) => Some(expr.keyword.line),
+            Expr::List(expr) => Some(expr.bracket.line),
+            Expr::Map(expr) => Some(expr.brace.line),
+            Expr::Index(expr) => expr.object.line(),
+            Expr::IndexSet(expr) => expr.object.line(),
+            Expr::Interpolation(expr) => Some(expr.start.line),
+            Expr::Coalesce(expr) => expr.left.line(),
         }
     }
 }
@@ -302,15 +576,37 @@ impl Hash for This {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct Super {
+    pub keyword: Token,
+    pub method: Token,
+}
+impl Super {
+    pub fn new(keyword: Token, method: Token) -> Self {
+        Self { keyword, method }
+    }
+}
+impl Eq for Super {}
+
+impl Hash for Super {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.keyword.hash(state);
+        self.method.hash(state);
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Set{
     pub object: Box<Expr>,
     pub name: Token,
     pub value: Box<Expr>,
+    /// For `obj.name op= value`: the binary operator that combines the old
+    /// field value with `value`. Kept here so `object` is evaluated only once.
+    pub operator: Option<Token>,
 }
 impl Set {
-    pub fn new(object: Box<Expr>, name: Token, value: Box<Expr>) -> Self {
-        Self { object, name, value }
+    pub fn new(object: Box<Expr>, name: Token, value: Box<Expr>, operator: Option<Token>) -> Self {
+        Self { object, name, value, operator }
     }
 }   
 impl Eq for Set {}
@@ -319,6 +615,7 @@ impl Hash for Set {
         self.object.hash(state);
         self.name.hash(state);
         self.value.hash(state);
+        self.operator.hash(state);
     }
 }
 
@@ -342,6 +639,109 @@ impl Hash for Get {
     }
 }
 
+/// List literal: `[a, b, c]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListExpr {
+    pub bracket: Token,
+    pub elements: Vec<Expr>,
+}
+impl ListExpr {
+    pub fn new(bracket: Token, elements: Vec<Expr>) -> Self {
+        Self { bracket, elements }
+    }
+}
+impl Eq for ListExpr {}
+impl Hash for ListExpr {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.bracket.hash(state);
+        self.elements.hash(state);
+    }
+}
+
+/// Map literal: `{key: value, ...}`. Only parsed where an expression is
+/// expected; a `{` that starts a statement is always a block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapExpr {
+    pub brace: Token,
+    pub entries: Vec<(Expr, Expr)>,
+}
+impl MapExpr {
+    pub fn new(brace: Token, entries: Vec<(Expr, Expr)>) -> Self {
+        Self { brace, entries }
+    }
+}
+impl Eq for MapExpr {}
+impl Hash for MapExpr {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.brace.hash(state);
+        self.entries.hash(state);
+    }
+}
+
+/// Index read: `object[index]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Index {
+    pub object: Box<Expr>,
+    pub bracket: Token,
+    pub index: Box<Expr>,
+}
+impl Index {
+    pub fn new(object: Box<Expr>, bracket: Token, index: Box<Expr>) -> Self {
+        Self { object, bracket, index }
+    }
+}
+impl Eq for Index {}
+impl Hash for Index {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.object.hash(state);
+        self.bracket.hash(state);
+        self.index.hash(state);
+    }
+}
+
+/// Index assignment: `object[index] = value`
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexSet {
+    pub object: Box<Expr>,
+    pub bracket: Token,
+    pub index: Box<Expr>,
+    pub value: Box<Expr>,
+}
+impl IndexSet {
+    pub fn new(object: Box<Expr>, bracket: Token, index: Box<Expr>, value: Box<Expr>) -> Self {
+        Self { object, bracket, index, value }
+    }
+}
+impl Eq for IndexSet {}
+impl Hash for IndexSet {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.object.hash(state);
+        self.bracket.hash(state);
+        self.index.hash(state);
+        self.value.hash(state);
+    }
+}
+
+/// Interpolated string: `"a ${b} c"`; the parts are stringified and joined
+#[derive(Debug, Clone, PartialEq)]
+pub struct Interpolation {
+    /// The first `INTERPOLATION` token, i.e. the string up to the first `${`
+    pub start: Token,
+    pub parts: Vec<Expr>,
+}
+impl Interpolation {
+    pub fn new(start: Token, parts: Vec<Expr>) -> Self {
+        Self { start, parts }
+    }
+}
+impl Eq for Interpolation {}
+impl Hash for Interpolation {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.start.hash(state);
+        self.parts.hash(state);
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Call{
     pub callee: Box<Expr>,
@@ -365,6 +765,30 @@ impl Call {
     }
 }
 
+/// `left ?? right`: `right` is evaluated only when `left` is nil
+#[derive(Debug, Clone, PartialEq)]
+pub struct Coalesce {
+    pub left: Box<Expr>,
+    pub operator: Token,
+    pub right: Box<Expr>,
+}
+
+impl Eq for Coalesce {}
+
+impl Hash for Coalesce {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.left.hash(state);
+        self.operator.hash(state);
+        self.right.hash(state);
+    }
+}
+
+impl Coalesce {
+    pub fn new(left: Box<Expr>, operator: Token, right: Box<Expr>) -> Self {
+        Self { left, operator, right }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct OR {
     pub left: Box<Expr>,
@@ -411,6 +835,60 @@ impl AND {
     }
 }
 
+// Function expression: fun (params) { body }, or fun name(params) { body }
+// where `name` is bound only inside the body so the function can recurse
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lambda {
+    pub keyword: Token,
+    pub name: Option<Token>,
+    pub params: Vec<Token>,
+    /// Default values of the trailing parameters, as in `FunctionStmt`
+    pub defaults: Vec<Expr>,
+    pub is_variadic: bool,
+    pub body: Vec<Stmt>,
+}
+
+impl Eq for Lambda {}
+
+impl Hash for Lambda {
+    // The `fun` token and parameters identify a lambda; the body isn't hashed
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.keyword.hash(state);
+        self.name.hash(state);
+        self.params.hash(state);
+    }
+}
+
+impl Lambda {
+    pub fn new(keyword: Token, name: Option<Token>, params: Vec<Token>, defaults: Vec<Expr>, is_variadic: bool, body: Vec<Stmt>) -> Self {
+        Self { keyword, name, params, defaults, is_variadic, body }
+    }
+}
+
+// Conditional expression: condition ? then_branch : else_branch
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conditional {
+    pub condition: Box<Expr>,
+    pub then_branch: Box<Expr>,
+    pub else_branch: Box<Expr>,
+}
+
+impl Eq for Conditional {}
+
+impl Hash for Conditional {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.condition.hash(state);
+        self.then_branch.hash(state);
+        self.else_branch.hash(state);
+    }
+}
+
+impl Conditional {
+    pub fn new(condition: Box<Expr>, then_branch: Box<Expr>, else_branch: Box<Expr>) -> Self {
+        Self { condition, then_branch, else_branch }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Assignment {
     pub name: Token,
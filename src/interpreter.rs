@@ -1,24 +1,105 @@
 use std::cell::RefCell;
 use std::hash::Hash;
+use std::io::{self, Write};
 use std::rc::Rc;
 use crate::expr::{Binary, Expr, ExprVisitor, Grouping, Literal, StmtVisitor,
-    Unary, IfStatement, BreakStmt, ContinueStmt, Call};
+    Unary, IfStatement, BreakStmt, BreakpointStmt, ContinueStmt, Call};
 use crate::error::{Result, ReturnError, RuntimeError, Error};
 use crate::environment::Environment;
-use crate::token::{LiteralType, Token, TokenType, Value};
+use crate::token::{LiteralType, Token, TokenType, Value, format_number};
 use crate::expr::{Stmt, FunctionStmt};
-use crate::callable::{LoxClass, LoxFunction, create_clock_function};
-use std::collections::HashMap;
+use crate::callable::{LoxCallable, LoxClass, LoxEnum, LoxFunction, LoxInstance, NativeFunction, create_clock_function, create_weak_function, create_deref_function,
+    create_encode_url_function, create_decode_url_function, create_name_of_function,
+    create_assert_type_function, create_is_prime_function, create_next_prime_function,
+    create_sqrt_function, create_floor_function, create_ceil_function, create_abs_function, create_pow_function,
+    create_len_function, create_substring_function, create_chr_function, create_ord_function,
+    create_format_number_function, create_next_function, create_pad_start_function, create_pad_end_function,
+    create_pad_center_function, create_push_function, create_pop_function,
+    create_diff_function, create_mod_positive_function, create_partition_function,
+    create_min_by_function, create_max_by_function, create_input_function,
+    create_sum_range_function, create_is_empty_function, create_type_function, create_exit_function,
+    create_str_function, create_num_function, create_bool_function, create_char_at_function,
+    create_name_function, create_arity_function, create_keys_function, create_has_function, create_eprint_function,
+    create_get_or_function, create_list_of_function, create_list_from_function,
+    create_take_function, create_drop_function, create_take_while_function, create_drop_while_function,
+    create_remove_function, create_clear_function, create_size_function,
+    create_count_function, create_count_where_function};
+use crate::parser::Parser;
+use crate::resolver::Resolver;
+use crate::scanner::Scanner;
+use std::collections::{HashMap, HashSet};
+
+/// Output sink that appends to a buffer the caller keeps a handle to
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
 
 /// Interpreter that evaluates expressions using the Visitor pattern
 #[derive(Clone)]
 pub struct Interpreter{
+    /// Outermost environment, where natives and top-level names live
+    pub globals: Rc<Environment>,
     pub environment: Rc<Environment>,
-    pub locals: HashMap<Expr, usize>,
+    /// Resolved variables: (scope distance, slot in that environment).
+    /// Shared so the copy made for every function call doesn't rebuild it.
+    pub locals: Rc<HashMap<Expr, (usize, usize)>>,
+    /// Written after every `print` (default "\n")
+    print_terminator: String,
+    /// Written between the values of `print a, b;` (default " ")
+    print_separator: String,
+    /// Where `print` and REPL echoes go; stdout unless replaced with `set_output`
+    output: Rc<RefCell<Box<dyn Write>>>,
+    /// Where `eprint` goes; stderr unless replaced with `set_error_output`
+    error_output: Rc<RefCell<Box<dyn Write>>>,
+    /// When set, `breakpoint;` opens a debug prompt on stdin
+    interactive: bool,
+    /// Values yielded so far by the generator body being run, if any
+    pub yielded: Option<Vec<Value>>,
+    /// Natives removed with `deny_native`; the names stay unusable at top level
+    denied_natives: HashSet<String>,
+    /// Called by the `exit` native; ends the process unless replaced with `set_exit_handler`
+    exit_handler: Rc<dyn Fn(i32)>,
+    /// Number of Lox calls in progress. Each call runs on a clone of the
+    /// interpreter with this one higher, so it drops back on return.
+    call_depth: usize,
+    /// Calls nested deeper than this raise "Stack overflow." (default 1000)
+    max_call_depth: usize,
+    /// `defer` blocks registered in each running block, innermost block last
+    deferred: Vec<Vec<Rc<Stmt>>>,
 }
 
 impl Interpreter {
     pub fn new() -> Self {
+        let environment = Self::new_globals();
+
+        Self {
+            globals: Rc::clone(&environment),
+            environment,
+            locals: Rc::new(HashMap::new()),
+            print_terminator: "\n".to_string(),
+            print_separator: " ".to_string(),
+            output: Rc::new(RefCell::new(Box::new(io::stdout()))),
+            error_output: Rc::new(RefCell::new(Box::new(io::stderr()))),
+            interactive: false,
+            yielded: None,
+            denied_natives: HashSet::new(),
+            exit_handler: Rc::new(|code| std::process::exit(code)),
+            call_depth: 0,
+            max_call_depth: 1000,
+            deferred: Vec::new(),
+        }
+    }
+
+    /// Build a fresh global environment with the native functions registered
+    fn new_globals() -> Rc<Environment> {
         let environment = Rc::new(Environment::new(None));
 
         // Register native functions
@@ -26,30 +107,362 @@ impl Interpreter {
             "clock".to_string(),
             Value::Callable(create_clock_function())
         );
+        environment.define("weak".to_string(), Value::Callable(create_weak_function()));
+        environment.define("deref".to_string(), Value::Callable(create_deref_function()));
+        environment.define("encode_url".to_string(), Value::Callable(create_encode_url_function()));
+        environment.define("decode_url".to_string(), Value::Callable(create_decode_url_function()));
+        environment.define("name_of".to_string(), Value::Callable(create_name_of_function()));
+        environment.define("assert_type".to_string(), Value::Callable(create_assert_type_function()));
+        environment.define("is_prime".to_string(), Value::Callable(create_is_prime_function()));
+        environment.define("next_prime".to_string(), Value::Callable(create_next_prime_function()));
+        environment.define("sqrt".to_string(), Value::Callable(create_sqrt_function()));
+        environment.define("floor".to_string(), Value::Callable(create_floor_function()));
+        environment.define("ceil".to_string(), Value::Callable(create_ceil_function()));
+        environment.define("abs".to_string(), Value::Callable(create_abs_function()));
+        environment.define("pow".to_string(), Value::Callable(create_pow_function()));
+        environment.define("len".to_string(), Value::Callable(create_len_function()));
+        environment.define("substring".to_string(), Value::Callable(create_substring_function()));
+        environment.define("chr".to_string(), Value::Callable(create_chr_function()));
+        environment.define("ord".to_string(), Value::Callable(create_ord_function()));
+        environment.define("format_number".to_string(), Value::Callable(create_format_number_function()));
+        environment.define("next".to_string(), Value::Callable(create_next_function()));
+        environment.define("pad_start".to_string(), Value::Callable(create_pad_start_function()));
+        environment.define("pad_end".to_string(), Value::Callable(create_pad_end_function()));
+        environment.define("pad_center".to_string(), Value::Callable(create_pad_center_function()));
+        environment.define("push".to_string(), Value::Callable(create_push_function()));
+        environment.define("pop".to_string(), Value::Callable(create_pop_function()));
+        environment.define("diff".to_string(), Value::Callable(create_diff_function()));
+        environment.define("mod_positive".to_string(), Value::Callable(create_mod_positive_function()));
+        environment.define("partition".to_string(), Value::Callable(create_partition_function()));
+        environment.define("min_by".to_string(), Value::Callable(create_min_by_function()));
+        environment.define("max_by".to_string(), Value::Callable(create_max_by_function()));
+        environment.define("input".to_string(), Value::Callable(create_input_function()));
+        environment.define("sum_range".to_string(), Value::Callable(create_sum_range_function()));
+        environment.define("is_empty".to_string(), Value::Callable(create_is_empty_function()));
+        environment.define("type".to_string(), Value::Callable(create_type_function()));
+        environment.define("exit".to_string(), Value::Callable(create_exit_function()));
+        environment.define("str".to_string(), Value::Callable(create_str_function()));
+        environment.define("num".to_string(), Value::Callable(create_num_function()));
+        environment.define("bool".to_string(), Value::Callable(create_bool_function()));
+        environment.define("charAt".to_string(), Value::Callable(create_char_at_function()));
+        environment.define("name".to_string(), Value::Callable(create_name_function()));
+        environment.define("arity".to_string(), Value::Callable(create_arity_function()));
+        environment.define("keys".to_string(), Value::Callable(create_keys_function()));
+        environment.define("has".to_string(), Value::Callable(create_has_function()));
+        environment.define("eprint".to_string(), Value::Callable(create_eprint_function()));
+        environment.define("get_or".to_string(), Value::Callable(create_get_or_function()));
+        environment.define("list_of".to_string(), Value::Callable(create_list_of_function()));
+        environment.define("list_from".to_string(), Value::Callable(create_list_from_function()));
+        environment.define("take".to_string(), Value::Callable(create_take_function()));
+        environment.define("drop".to_string(), Value::Callable(create_drop_function()));
+        environment.define("take_while".to_string(), Value::Callable(create_take_while_function()));
+        environment.define("drop_while".to_string(), Value::Callable(create_drop_while_function()));
+        environment.define("remove".to_string(), Value::Callable(create_remove_function()));
+        environment.define("clear".to_string(), Value::Callable(create_clear_function()));
+        environment.define("size".to_string(), Value::Callable(create_size_function()));
+        environment.define("count".to_string(), Value::Callable(create_count_function()));
+        environment.define("count_where".to_string(), Value::Callable(create_count_where_function()));
+        environment
+    }
 
-        Self {
-            environment,
-            locals: HashMap::new(),
+    /// Drop every global and resolved local so the next script starts clean.
+    /// The builtin natives are re-registered; ones added with `register_native` are not.
+    pub fn reset(&mut self) {
+        self.globals = Self::new_globals();
+        self.environment = Rc::clone(&self.globals);
+        self.locals = Rc::new(HashMap::new());
+        self.yielded = None;
+        for name in &self.denied_natives {
+            self.globals.remove(name);
+        }
+    }
+
+    /// Change what `print` writes after each value
+    pub fn set_print_terminator(&mut self, terminator: &str) {
+        self.print_terminator = terminator.to_string();
+    }
+
+    /// Change what `print` writes between the values of `print a, b;`
+    pub fn set_print_separator(&mut self, separator: &str) {
+        self.print_separator = separator.to_string();
+    }
+
+    /// Send `print` output to `output` instead of stdout, e.g. a buffer in tests
+    pub fn set_output(&mut self, output: Box<dyn Write>) {
+        self.output = Rc::new(RefCell::new(output));
+    }
+
+    /// Send `eprint` output to `output` instead of stderr
+    pub fn set_error_output(&mut self, output: Box<dyn Write>) {
+        self.error_output = Rc::new(RefCell::new(output));
+    }
+
+    /// Write `text` to the output sink
+    pub fn write_output(&self, text: &str) -> io::Result<()> {
+        self.output.borrow_mut().write_all(text.as_bytes())
+    }
+
+    /// Write `text` to the error sink
+    pub fn write_error(&self, text: &str) -> io::Result<()> {
+        self.error_output.borrow_mut().write_all(text.as_bytes())
+    }
+
+    /// Flush the output sink, e.g. before the process exits
+    pub fn flush_output(&self) -> io::Result<()> {
+        self.output.borrow_mut().flush()
+    }
+
+    /// Enable or disable the `breakpoint;` debug prompt
+    pub fn set_interactive(&mut self, interactive: bool) {
+        self.interactive = interactive;
+    }
+
+    /// Limit how deeply Lox calls may nest before a "Stack overflow." runtime
+    /// error, so runaway recursion fails cleanly instead of aborting the process
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        self.max_call_depth = depth;
+    }
+
+    /// Count one more Lox call on this interpreter, the clone the call runs on.
+    /// Every way of running a `LoxFunction` goes through here.
+    pub fn enter_call(&mut self, token: &Token) -> Result<()> {
+        self.check_call_depth(token)?;
+        self.call_depth += 1;
+        Ok(())
+    }
+
+    fn check_call_depth(&self, token: &Token) -> Result<()> {
+        if self.call_depth >= self.max_call_depth {
+            return Err(Error::RuntimeError(RuntimeError::new(token.clone(), "Stack overflow.".to_string())));
+        }
+        Ok(())
+    }
+
+    /// Replace what `exit(code)` does, e.g. to record the code in tests instead
+    /// of ending the process. If the handler returns, the script keeps running.
+    pub fn set_exit_handler<F>(&mut self, handler: F)
+    where
+        F: Fn(i32) + 'static,
+    {
+        self.exit_handler = Rc::new(handler);
+    }
+
+    /// Run the exit handler; by default this never returns
+    pub fn exit(&self, code: i32) {
+        (self.exit_handler)(code);
+    }
+
+    /// Debug prompt opened by `breakpoint;`: inspect variables in the current
+    /// environment until the user types `continue` (or stdin closes)
+    fn debug_prompt(&self, line: usize) {
+        use std::io::{self, BufRead, Write};
+        println!("Breakpoint at line {}. Enter a variable name, 'env' or 'continue'.", line);
+        let stdin = io::stdin();
+        loop {
+            print!("(debug) ");
+            let _ = io::stdout().flush();
+            let mut input = String::new();
+            match stdin.lock().read_line(&mut input) {
+                Ok(0) | Err(_) => return,
+                Ok(_) => {}
+            }
+            match input.trim() {
+                "" => {}
+                "c" | "continue" => return,
+                "env" => {
+                    for (name, value) in self.current_scope_snapshot() {
+                        println!("{} = {}", name, self.stringify(&value));
+                    }
+                }
+                name => match self.environment.get(&Token::new(TokenType::IDENTIFIER, name.to_string(), line, 0, None)) {
+                    Ok(value) => println!("{}", self.stringify(&value)),
+                    Err(Error::RuntimeError(error)) => println!("{}", error.message),
+                    Err(_) => {}
+                },
+            }
         }
     }
 
+    /// Every variable visible from the current environment, innermost scope
+    /// first. A name shadowed by an inner scope appears only once, with the
+    /// inner value.
+    pub fn current_scope_snapshot(&self) -> Vec<(String, Value)> {
+        let mut seen = HashSet::new();
+        let mut snapshot = Vec::new();
+        let mut environment = Some(Rc::clone(&self.environment));
+        while let Some(env) = environment {
+            let mut bindings = env.bindings();
+            bindings.sort_by(|a, b| a.0.cmp(&b.0));
+            for (name, value) in bindings {
+                if seen.insert(name.clone()) {
+                    snapshot.push((name, value));
+                }
+            }
+            environment = env.enclosing.clone();
+        }
+        snapshot
+    }
+
     /// Main entry point for interpreting an expression
     pub fn evaluate(&mut self, expr: &Expr) -> Result<Value> {
         expr.accept(self)
     }
 
-    pub fn interpret(&mut self, statements: &Vec<Stmt>) -> Result<()> {
+    /// Run top-level statements. With `repl_mode`, the value of each expression
+    /// statement is printed, except for assignments to variables, fields and indexes.
+    pub fn interpret(&mut self, statements: &Vec<Stmt>, repl_mode: bool) -> Result<()> {
         for stmt in statements {
-            self.execute(stmt)?;
+            let value = self.execute(stmt)?;
+            if repl_mode && let Stmt::Expression(expression) = stmt {
+                match *expression.expression {
+                    Expr::Assignment(_) | Expr::Set(_) | Expr::IndexSet(_) => {}
+                    _ => {
+                        let text = format!("{}\n", self.stringify_mut(&value)?);
+                        // REPL の表示に失敗しても実行は続ける
+                        let _ = self.write_output(&text);
+                    }
+                }
+            }
         }
         Ok(())
     }
+    /// Scan, parse, resolve and run `source`, returning the value of the last
+    /// expression statement (or nil if there is none)
+    pub fn eval_source(&mut self, source: &str) -> Result<Value> {
+        let mut scanner = Scanner::new(source);
+        let tokens = scanner.try_scan_tokens().map_err(|errors| {
+            Error::SyntaxError(errors.iter().map(|error| error.to_string()).collect())
+        })?;
+        let statements = Parser::new(tokens).parse().map_err(|parse_errors| {
+            Error::SyntaxError(parse_errors.iter().map(|error| error.to_string()).collect())
+        })?;
+
+        let mut resolver = Resolver::new(self);
+        resolver.resolve_statements(&statements);
+        if !resolver.errors.is_empty() {
+            return Err(Error::SyntaxError(resolver.errors.iter().map(|error| error.to_string()).collect()));
+        }
+        let mut last = Value::Nil;
+        for stmt in &statements {
+            let value = self.execute(stmt)?;
+            if let Stmt::Expression(_) = stmt {
+                last = value;
+            }
+        }
+        Ok(last)
+    }
+
+    /// Run `source` in a fresh interpreter, returning everything it printed
+    /// and the error that stopped it, if any. Meant for tests of Lox programs.
+    pub fn run_and_capture(source: &str) -> (String, Option<Error>) {
+        let buffer = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(Box::new(SharedBuffer(Rc::clone(&buffer))));
+        let error = interpreter.eval_source(source).err();
+        let output = String::from_utf8_lossy(&buffer.borrow()).into_owned();
+        (output, error)
+    }
+
+    /// Look up a top-level variable by name
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.globals.get(&Token::new(TokenType::IDENTIFIER, name.to_string(), 0, 0, None)).ok()
+    }
+
+    /// Define (or overwrite) a top-level variable, e.g. to pass data in from the host
+    pub fn set_global(&mut self, name: &str, value: Value) {
+        self.globals.define(name.to_string(), value);
+    }
+
+    /// Define a host closure as a native function in the global environment
+    pub fn register_native<F>(&mut self, name: &str, arity: usize, func: F)
+    where
+        F: Fn(Vec<Value>) -> Result<Value> + 'static,
+    {
+        let native = NativeFunction::new(name, arity, Rc::new(func));
+        self.set_global(name, Value::Callable(LoxCallable::NativeFunction(native)));
+    }
+
+    /// Call `callee` from the host, e.g. a callback a script stored in a global.
+    /// Fails with a runtime error on an arity mismatch or a non-callable value.
+    pub fn call_value(&mut self, callee: Value, arguments: Vec<Value>) -> Result<Value> {
+        let paren = Token::new(TokenType::RIGHT_PAREN, ")".to_string(), 0, 0, None);
+        self.call(&paren, callee, arguments)
+    }
+
+    /// Check the arity and invoke `callee`; `paren` locates errors
+    fn call(&mut self, paren: &Token, callee: Value, arguments: Vec<Value>) -> Result<Value> {
+        // 深さを数えるのは LoxFunction::call だが、ここで先に調べればエラー位置が呼び出し箇所になる
+        self.check_call_depth(paren)?;
+        let inner = self.clone();
+        // calleeがCallableかチェック
+        match callee {
+            Value::Callable(function) => {
+                // 引数の数をチェック
+                if !function.accepts(arguments.len()) {
+                    return Err(Error::RuntimeError(RuntimeError::new(
+                        paren.clone(),
+                        format!(
+                            "Expected {} arguments but got {}.",
+                            function.arity_description(),
+                            arguments.len()
+                        ),
+                    )));
+                }
+    
+                // 関数を呼び出す
+                function.call(arguments, Some(RefCell::new(inner)))
+            }
+            Value::Class(class) => {
+                // クラスのコンストラクタを呼び出す
+                use crate::callable::Callable;
+                if !class.accepts(arguments.len()) {
+                    return Err(Error::RuntimeError(RuntimeError::new(
+                        paren.clone(),
+                        format!(
+                            "Expected {} arguments but got {}.",
+                            class.arity_description(),
+                            arguments.len()
+                        ),
+                    )));
+                }
+                class.call(arguments, Some(RefCell::new(inner)))
+            }
+            _ => Err(Error::RuntimeError(RuntimeError::new(
+                paren.clone(),
+                "Can only call functions and classes.".to_string(),
+            ))),
+        }
+    }
+
+    /// Remove a native from the globals for sandboxing. Afterwards any script
+    /// that calls, assigns or redefines `name` at top level gets a runtime error.
+    pub fn deny_native(&mut self, name: &str) {
+        self.globals.remove(name);
+        self.denied_natives.insert(name.to_string());
+    }
+
+    /// Fail if `name` refers to a native disabled with `deny_native`
+    fn check_not_denied(&self, name: &Token) -> Result<()> {
+        if self.denied_natives.contains(&name.lexeme) {
+            return Err(Error::RuntimeError(RuntimeError::new(
+                name.clone(),
+                format!("Native '{}' is disabled.", name.lexeme),
+            )));
+        }
+        Ok(())
+    }
+
+    fn at_top_level(&self) -> bool {
+        Rc::ptr_eq(&self.environment, &self.globals)
+    }
+
     pub fn execute(&mut self, stmt: &Stmt) -> Result<Value> {
         stmt.accept(self)
     }
 
     pub fn execute_block(&mut self, statements: &Vec<Stmt>, env: Rc<Environment>) -> Result<()> {
         let previous = std::mem::replace(&mut self.environment, env);
+        self.deferred.push(Vec::new());
 
         // ブロック内のステートメントを実行
         let result = (|| {
@@ -62,39 +475,318 @@ impl Interpreter {
             Ok(())
         })();
 
+        // defer はブロックの環境のまま、登録と逆順に実行する
+        let deferred = self.deferred.pop().unwrap_or_default();
+        let result = self.run_deferred(&deferred, result);
+
         // 元のenvironmentに戻す
         self.environment = previous;
         result
     }
 
+    /// Run a block's `defer` bodies, last registered first. All of them run even
+    /// if one fails; the block's own error or `return` wins, otherwise the first
+    /// error or `return` from a deferred body becomes the block's result.
+    fn run_deferred(&mut self, deferred: &[Rc<Stmt>], mut result: Result<()>) -> Result<()> {
+        for body in deferred.iter().rev() {
+            let res = self.execute(body);
+            if result.is_ok() && let Err(err) = res {
+                result = Err(err);
+            }
+        }
+        result
+    }
+
+    /// Like `stringify`, but an instance whose class defines `toString()` is
+    /// printed as the result of calling that method
+    pub fn stringify_mut(&mut self, value: &Value) -> Result<String> {
+        if let Value::Instance(instance) = value {
+            let method = instance.borrow().class.find_method("toString").cloned();
+            if let Some(method) = method
+                && let Value::Callable(to_string) = method.bind(Rc::clone(instance))
+            {
+                if !to_string.accepts(0) {
+                    return Err(Error::RuntimeError(RuntimeError::new(
+                        Token::new(TokenType::IDENTIFIER, "toString".to_string(), 0, 0, None),
+                        format!("Method 'toString' must take no arguments but takes {}.", to_string.arity_description()),
+                    )));
+                }
+                let result = to_string.call(Vec::new(), Some(RefCell::new(self.clone())))?;
+                return Ok(self.stringify(&result));
+            }
+        }
+        Ok(self.stringify(value))
+    }
+
     pub fn stringify(&self, value: &Value) -> String {
         match value {
             Value::Nil => "nil".to_string(),
-            Value::Number(n) => {
-                let mut text = n.to_string();
-                if text.ends_with(".0") {
-                    text.truncate(text.len() - 2);
-                }
-                text
-            },
+            Value::Number(n) => format_number(*n),
             _ => format!("{}", value),
         }
     }
 
-    pub fn resolve(&mut self, expr: Expr, depth: usize) {
-        self.locals.insert(expr.clone(), depth);
+    pub fn resolve(&mut self, expr: Expr, depth: usize, slot: usize) {
+        Rc::make_mut(&mut self.locals).insert(expr, (depth, slot));
+    }
+
+    pub fn look_up_variable(&self, name: &Token, expr: &Expr) -> Result<Value> {
+        if let Some(&(distance, slot)) = self.locals.get(expr) {
+            self.environment.get_at(distance, slot, name)
+        } else {
+            self.globals.get(name)
+        }
+    }
+
+    /// Check that a map key is a string
+    fn map_key(token: &Token, key: &Value) -> Result<String> {
+        match key {
+            Value::String(key) => Ok(key.clone()),
+            _ => Err(Error::RuntimeError(RuntimeError::new(
+                token.clone(),
+                "Map keys must be strings.".to_string(),
+            ))),
+        }
+    }
+
+    /// Check that `index` is an integer within `0..len`
+    fn list_index(bracket: &Token, index: &Value, len: usize) -> Result<usize> {
+        match index {
+            Value::Number(n) if n.fract() == 0.0 && *n >= 0.0 && (*n as usize) < len => Ok(*n as usize),
+            Value::Number(n) => Err(Error::RuntimeError(RuntimeError::new(
+                bracket.clone(),
+                format!("Index {} out of range for list of length {}.", n, len),
+            ))),
+            _ => Err(Error::RuntimeError(RuntimeError::new(
+                bracket.clone(),
+                "List index must be a number.".to_string(),
+            ))),
+        }
+    }
+
+    /// Call the class's `on_set(name, old, new)` observer after a field changes.
+    /// Sets made from inside the observer don't re-trigger it.
+    fn notify_on_set(&mut self, instance: &Rc<RefCell<LoxInstance>>, name: &Token, old: Value, new: Value) -> Result<()> {
+        use crate::callable::Callable;
+        let observer = {
+            let instance = instance.borrow();
+            if instance.in_on_set {
+                return Ok(());
+            }
+            match instance.class.find_method("on_set") {
+                Some(method) => method.clone(),
+                None => return Ok(()),
+            }
+        };
+        if observer.arity() != 3 {
+            return Err(Error::RuntimeError(RuntimeError::new(
+                name.clone(),
+                format!("Expected 'on_set' to take 3 arguments but it takes {}.", observer.arity()),
+            )));
+        }
+        let callback = match observer.bind(Rc::clone(instance)) {
+            Value::Callable(callback) => callback,
+            _ => return Ok(()),
+        };
+
+        instance.borrow_mut().in_on_set = true;
+        let result = self.call(
+            name,
+            Value::Callable(callback),
+            vec![Value::String(name.lexeme.clone()), old, new],
+        );
+        instance.borrow_mut().in_on_set = false;
+        result.map(|_| ())
+    }
+
+    /// Apply a binary operator to two already-evaluated operands
+    fn apply_binary(&self, operator: &Token, left: Value, right: Value) -> Result<Value> {
+        match operator.type_ {
+            // Arithmetic operators
+            TokenType::MINUS => {
+                match (left, right) {
+                    (Value::Number(l), Value::Number(r)) => Ok(Value::Number(l - r)),
+                    _ => Err(Error::RuntimeError(RuntimeError::new(
+                        operator.clone(),
+                        "Operands must be numbers.".to_string(),
+                    ))),
+                }
+            }
+            TokenType::SLASH => {
+                match (left, right) {
+                    (Value::Number(l), Value::Number(r)) => {
+                        if r == 0.0 {
+                            Err(Error::RuntimeError(RuntimeError::new(
+                                operator.clone(),
+                                "Division by zero.".to_string(),
+                            )))
+                        } else {
+                            Ok(Value::Number(l / r))
+                        }
+                    }
+                    _ => Err(Error::RuntimeError(RuntimeError::new(
+                        operator.clone(),
+                        "Operands must be numbers.".to_string(),
+                    ))),
+                }
+            }
+            TokenType::DIV => {
+                match (left, right) {
+                    (Value::Number(l), Value::Number(r)) => {
+                        if r == 0.0 {
+                            Err(Error::RuntimeError(RuntimeError::new(
+                                operator.clone(),
+                                "Division by zero.".to_string(),
+                            )))
+                        } else {
+                            Ok(Value::Number((l / r).floor()))
+                        }
+                    }
+                    _ => Err(Error::RuntimeError(RuntimeError::new(
+                        operator.clone(),
+                        "Operands must be numbers.".to_string(),
+                    ))),
+                }
+            }
+            TokenType::STAR => {
+                match (left, right) {
+                    (Value::Number(l), Value::Number(r)) => Ok(Value::Number(l * r)),
+                    _ => Err(Error::RuntimeError(RuntimeError::new(
+                        operator.clone(),
+                        "Operands must be numbers.".to_string(),
+                    ))),
+                }
+            }
+            TokenType::STAR_STAR => {
+                match (left, right) {
+                    (Value::Number(l), Value::Number(r)) => Ok(Value::Number(l.powf(r))),
+                    _ => Err(Error::RuntimeError(RuntimeError::new(
+                        operator.clone(),
+                        "Operands must be numbers.".to_string(),
+                    ))),
+                }
+            }
+            TokenType::PLUS => {
+                match (left, right) {
+                    (Value::Number(l), Value::Number(r)) => Ok(Value::Number(l + r)),
+                    (Value::String(l), Value::String(r)) => Ok(Value::String(format!("{}{}", l, r))),
+                    (Value::String(l), Value::Number(r)) => Ok(Value::String(format!("{}{}", l, r))),
+                    (Value::Number(l), Value::String(r)) => Ok(Value::String(format!("{}{}", l, r))),
+                    _ => Err(Error::RuntimeError(RuntimeError::new(
+                        operator.clone(),
+                        "Operands must be two numbers or two strings.".to_string(),
+                    ))),
+                }
+            }
+
+            // Comparison operators
+            TokenType::GREATER => {
+                match (left, right) {
+                    (Value::Number(l), Value::Number(r)) => Ok(Value::Bool(l > r)),
+                    (Value::String(l), Value::String(r)) => Ok(Value::Bool(l > r)),
+                    _ => Err(Error::RuntimeError(RuntimeError::new(
+                        operator.clone(),
+                        "Operands must be two numbers or two strings.".to_string(),
+                    ))),
+                }
+            }
+            TokenType::GREATER_EQUAL => {
+                match (left, right) {
+                    (Value::Number(l), Value::Number(r)) => Ok(Value::Bool(l >= r)),
+                    (Value::String(l), Value::String(r)) => Ok(Value::Bool(l >= r)),
+                    _ => Err(Error::RuntimeError(RuntimeError::new(
+                        operator.clone(),
+                        "Operands must be two numbers or two strings.".to_string(),
+                    ))),
+                }
+            }
+            TokenType::LESS => {
+                match (left, right) {
+                    (Value::Number(l), Value::Number(r)) => Ok(Value::Bool(l < r)),
+                    (Value::String(l), Value::String(r)) => Ok(Value::Bool(l < r)),
+                    _ => Err(Error::RuntimeError(RuntimeError::new(
+                        operator.clone(),
+                        "Operands must be two numbers or two strings.".to_string(),
+                    ))),
+                }
+            }
+            TokenType::LESS_EQUAL => {
+                match (left, right) {
+                    (Value::Number(l), Value::Number(r)) => Ok(Value::Bool(l <= r)),
+                    (Value::String(l), Value::String(r)) => Ok(Value::Bool(l <= r)),
+                    _ => Err(Error::RuntimeError(RuntimeError::new(
+                        operator.clone(),
+                        "Operands must be two numbers or two strings.".to_string(),
+                    ))),
+                }
+            }
+
+            // Equality operators
+            TokenType::BANG_EQUAL => {
+                Ok(Value::Bool(!self.is_equal(&left, &right)))
+            }
+            TokenType::EQUAL_EQUAL => {
+                Ok(Value::Bool(self.is_equal(&left, &right)))
+            }
+
+            // Bitwise operators
+            TokenType::AMPERSAND | TokenType::PIPE | TokenType::CARET
+            | TokenType::LESS_LESS | TokenType::GREATER_GREATER => {
+                let (l, r) = Self::integer_operands(operator, &left, &right)?;
+                let result = match operator.type_ {
+                    TokenType::AMPERSAND => l & r,
+                    TokenType::PIPE => l | r,
+                    TokenType::CARET => l ^ r,
+                    _ => {
+                        if !(0..64).contains(&r) {
+                            return Err(Error::RuntimeError(RuntimeError::new(
+                                operator.clone(),
+                                "Shift amount must be between 0 and 63.".to_string(),
+                            )));
+                        }
+                        if operator.type_ == TokenType::LESS_LESS { l << r } else { l >> r }
+                    }
+                };
+                Ok(Value::Number(result as f64))
+            }
+
+            _ => Err(Error::RuntimeError(RuntimeError::new(
+                operator.clone(),
+                format!("Unknown binary operator: {:?}", operator.type_),
+            ))),
+        }
+    }
+
+    /// Helper: both operands of a bitwise operator as integers
+    fn integer_operands(operator: &Token, left: &Value, right: &Value) -> Result<(i64, i64)> {
+        match (left, right) {
+            (Value::Number(l), Value::Number(r)) if l.fract() == 0.0 && r.fract() == 0.0 => Ok((*l as i64, *r as i64)),
+            _ => Err(Error::RuntimeError(RuntimeError::new(
+                operator.clone(),
+                "Operands must be integers.".to_string(),
+            ))),
+        }
+    }
+
+    /// Helper: a labeled `break`/`continue` carries its label as the literal of
+    /// the token it propagates with
+    fn label_literal(label: &Option<Token>) -> Option<LiteralType> {
+        label.as_ref().map(|label| LiteralType::String(label.lexeme.clone()))
     }
 
-    pub fn look_up_variable(&self, name: &String, expr: &Expr) -> Result<Value> {
-        if let Some(distance) = self.locals.get(expr) {
-            self.environment.get_at(*distance, name)
-        } else {
-            self.environment.get(&name)
+    /// Helper: should the loop labeled `label` handle this `break`/`continue`?
+    /// Unlabeled ones stop at the innermost loop; labeled ones pass through
+    /// loops until they reach the one with that label.
+    fn targets_loop(signal: &RuntimeError, label: &Option<Token>) -> bool {
+        match (&signal.token.literal, label) {
+            (None, _) => true,
+            (Some(LiteralType::String(target)), Some(label)) => *target == label.lexeme,
+            _ => false,
         }
     }
 
     /// Helper: Check if a value is truthy (Lox semantics: nil and false are falsey)
-    fn is_truthy(&self, value: &Value) -> bool {
+    pub fn is_truthy(&self, value: &Value) -> bool {
         match value {
             Value::Nil => false,
             Value::Bool(b) => *b,
@@ -102,7 +794,10 @@ impl Interpreter {
         }
     }
 
-    /// Helper: Check if two values are equal
+    /// Helper: Lox `==`. Numbers use IEEE equality, so `nan != nan` and
+    /// `0 == -0`. Lists and maps compare by contents (cycles are bounded),
+    /// but a collection is checked for identity first: with `a = [nan]`,
+    /// `a == a` is true while `[nan] == [nan]` is false.
     fn is_equal(&self, left: &Value, right: &Value) -> bool {
         left == right
     }
@@ -120,12 +815,19 @@ impl Interpreter {
 
 impl StmtVisitor<Result<Value>> for Interpreter {
     fn visit_expression_stmt(&mut self, stmt: &crate::expr::Expression) -> Result<Value> {
-        self.evaluate(&stmt.expression);
-        Ok(Value::Nil)
+        self.evaluate(&stmt.expression)
     }
     fn visit_print_stmt(&mut self, stmt: &crate::expr::Print) -> Result<Value> {
-        let value = self.evaluate(&stmt.expression)?;
-        println!("{}", self.stringify(&value));
+        let mut parts = Vec::new();
+        for expression in &stmt.expressions {
+            let value = self.evaluate(expression)?;
+            parts.push(self.stringify_mut(&value)?);
+        }
+        let text = format!("{}{}", parts.join(&self.print_separator), self.print_terminator);
+        self.write_output(&text).map_err(|e| Error::RuntimeError(RuntimeError::new(
+            stmt.keyword.clone(),
+            format!("Could not write output: {}.", e),
+        )))?;
         Ok(Value::Nil)
     }
     fn visit_var_decl(&mut self, var_decl: &crate::expr::VarDecl) -> Result<Value> {
@@ -134,7 +836,33 @@ impl StmtVisitor<Result<Value>> for Interpreter {
         } else {
             Value::Nil
         };
-        self.environment.define(var_decl.name.lexeme.clone(), value.clone());
+        if var_decl.unpack.is_empty() {
+            if self.at_top_level() {
+                self.check_not_denied(&var_decl.name)?;
+            }
+            self.environment.define(var_decl.name.lexeme.clone(), value.clone());
+            return Ok(value);
+        }
+        let names: Vec<&Token> = std::iter::once(&var_decl.name).chain(&var_decl.unpack).collect();
+        let elements = match &value {
+            Value::List(list) => list.borrow().clone(),
+            _ => return Err(Error::RuntimeError(RuntimeError::new(
+                var_decl.name.clone(),
+                format!("Can only unpack a list, got {}.", value.type_name()),
+            ))),
+        };
+        if elements.len() != names.len() {
+            return Err(Error::RuntimeError(RuntimeError::new(
+                var_decl.name.clone(),
+                format!("Expected {} values to unpack but got {}.", names.len(), elements.len()),
+            )));
+        }
+        for (name, element) in names.into_iter().zip(elements) {
+            if self.at_top_level() {
+                self.check_not_denied(name)?;
+            }
+            self.environment.define(name.lexeme.clone(), element);
+        }
         Ok(value)
     }
     fn visit_block_stmt(&mut self, block: &crate::expr::Block) -> Result<Value> {
@@ -151,6 +879,44 @@ impl StmtVisitor<Result<Value>> for Interpreter {
         }
         Ok(Value::Nil)
     }
+    fn visit_defer_stmt(&mut self, defer_stmt: &crate::expr::DeferStmt) -> Result<Value> {
+        if let Some(frame) = self.deferred.last_mut() {
+            frame.push(Rc::clone(&defer_stmt.body));
+        }
+        Ok(Value::Nil)
+    }
+    fn visit_assert_stmt(&mut self, assert_stmt: &crate::expr::AssertStmt) -> Result<Value> {
+        let condition = self.evaluate(&assert_stmt.condition)?;
+        if self.is_truthy(&condition) {
+            return Ok(Value::Nil);
+        }
+        // メッセージは失敗したときだけ評価する
+        let message = match &assert_stmt.message {
+            Some(message) => {
+                let value = self.evaluate(message)?;
+                self.stringify_mut(&value)?
+            }
+            None => "Assertion failed.".to_string(),
+        };
+        Err(Error::RuntimeError(RuntimeError::new(assert_stmt.keyword.clone(), message)))
+    }
+    fn visit_switch_stmt(&mut self, switch_stmt: &crate::expr::SwitchStmt) -> Result<Value> {
+        let discriminant = self.evaluate(&switch_stmt.discriminant)?;
+        let mut arm = switch_stmt.default.as_ref();
+        for (value, body) in &switch_stmt.cases {
+            let value = self.evaluate(value)?;
+            if self.is_equal(&discriminant, &value) {
+                arm = Some(body);
+                break;
+            }
+        }
+        // フォールスルーはしない。一致したアームだけを実行する
+        if let Some(body) = arm {
+            let new_env = Rc::new(Environment::new(Some(Rc::clone(&self.environment))));
+            self.execute_block(body, new_env)?;
+        }
+        Ok(Value::Nil)
+    }
     fn visit_while_stmt(&mut self, while_stmt: &crate::expr::WhileStmt) -> Result<Value> {
         while {
             let condition = self.evaluate(&while_stmt.condition)?;
@@ -162,10 +928,10 @@ impl StmtVisitor<Result<Value>> for Interpreter {
             if let Err(err) = res {
                 match err {
                     Error::RuntimeError(runtime_err) => match runtime_err.token.type_ {
-                        TokenType::BREAK => {
+                        TokenType::BREAK if Self::targets_loop(&runtime_err, &while_stmt.label) => {
                             is_break = true;
                         },
-                        TokenType::CONTINUE => {
+                        TokenType::CONTINUE if Self::targets_loop(&runtime_err, &while_stmt.label) => {
                             is_continue = true;
                         },
                         _ => return Err(Error::RuntimeError(runtime_err)),
@@ -182,6 +948,26 @@ impl StmtVisitor<Result<Value>> for Interpreter {
         }
         Ok(Value::Nil)
     }
+    fn visit_do_while_stmt(&mut self, do_while_stmt: &crate::expr::DoWhileStmt) -> Result<Value> {
+        loop {
+            // continue は条件の評価へ進む
+            if let Err(err) = self.execute(&do_while_stmt.body) {
+                match err {
+                    Error::RuntimeError(runtime_err) => match runtime_err.token.type_ {
+                        TokenType::BREAK if Self::targets_loop(&runtime_err, &do_while_stmt.label) => break,
+                        TokenType::CONTINUE if Self::targets_loop(&runtime_err, &do_while_stmt.label) => {},
+                        _ => return Err(Error::RuntimeError(runtime_err)),
+                    },
+                    _ => return Err(err),
+                }
+            }
+            let condition = self.evaluate(&do_while_stmt.condition)?;
+            if !self.is_truthy(&condition) {
+                break;
+            }
+        }
+        Ok(Value::Nil)
+    }
     fn visit_for_stmt(&mut self, for_stmt: &crate::expr::ForStmt) -> Result<Value> {
         // Create a new environment for the for loop
         let loop_env = Rc::new(Environment::new(Some(Rc::clone(&self.environment))));
@@ -206,12 +992,11 @@ impl StmtVisitor<Result<Value>> for Interpreter {
                 if let Err(err) = res{
                     match err {
                         Error::RuntimeError(runtime_err) => match runtime_err.token.type_ {
-                            TokenType::BREAK => {
+                            TokenType::BREAK if Self::targets_loop(&runtime_err, &for_stmt.label) => {
                                 break;
                             },
-                            TokenType::CONTINUE => {
-                                continue;
-                            },
+                            // continue はループ本体を抜けるだけ。下の増分式は必ず実行する
+                            TokenType::CONTINUE if Self::targets_loop(&runtime_err, &for_stmt.label) => {},
                             _ => return Err(Error::RuntimeError(runtime_err)),
                         },
                         _ => return Err(err),
@@ -232,46 +1017,112 @@ impl StmtVisitor<Result<Value>> for Interpreter {
         result
     }
     fn visit_class_decl(&mut self, class_decl: &crate::expr::ClassDecl) -> Result<Value> {
+        if self.at_top_level() {
+            self.check_not_denied(&class_decl.name)?;
+        }
+        let superclass = match &class_decl.superclass {
+            Some(superclass_expr) => {
+                if superclass_expr.name.lexeme == class_decl.name.lexeme {
+                    return Err(Error::RuntimeError(RuntimeError::new(
+                        superclass_expr.name.clone(),
+                        "A class can't inherit from itself.".to_string(),
+                    )));
+                }
+                match self.evaluate(&Expr::Variable(superclass_expr.clone()))? {
+                    Value::Class(superclass) => Some(Rc::new(superclass)),
+                    _ => return Err(Error::RuntimeError(RuntimeError::new(
+                        superclass_expr.name.clone(),
+                        "Superclass must be a class.".to_string(),
+                    ))),
+                }
+            }
+            None => None,
+        };
+
         self.environment.define(class_decl.name.lexeme.clone(), Value::Nil);
 
+        // スーパークラスがある場合は "super" を束縛した環境をメソッドのクロージャにする
+        let enclosing = Rc::clone(&self.environment);
+        if let Some(superclass) = &superclass {
+            self.environment = Rc::new(Environment::new(Some(Rc::clone(&enclosing))));
+            self.environment.define("super".to_string(), Value::Class((**superclass).clone()));
+        }
+
         let mut methods = HashMap::new();
         for method in &class_decl.methods {
-            let func = LoxFunction::new(
+            let mut func = LoxFunction::new(
                 method.name.lexeme.clone(),
                 method.params.iter().map(|param| param.lexeme.clone()).collect(),
                 (*method.body).clone(),
                 Rc::clone(&self.environment),
                 method.name.lexeme == "init",
             );
+            func.is_getter = method.is_getter;
+            func.defaults = Rc::new(method.defaults.clone());
+            func.is_variadic = method.is_variadic;
             methods.insert(method.name.lexeme.clone(), func);
         }
-        println!("Defined class: {}", class_decl.name.lexeme);
-        let kclass = LoxClass::new(class_decl.name.lexeme.clone(), methods);
-        self.environment.put(&class_decl.name.lexeme, Value::Class(kclass))?;
+        let mut class_methods = HashMap::new();
+        for method in &class_decl.class_methods {
+            let mut func = LoxFunction::new(
+                method.name.lexeme.clone(),
+                method.params.iter().map(|param| param.lexeme.clone()).collect(),
+                (*method.body).clone(),
+                Rc::clone(&self.environment),
+                false,
+            );
+            func.defaults = Rc::new(method.defaults.clone());
+            func.is_variadic = method.is_variadic;
+            class_methods.insert(method.name.lexeme.clone(), func);
+        }
+        let kclass = LoxClass::new(class_decl.name.lexeme.clone(), superclass, methods, class_methods);
+        self.environment = enclosing;
+        self.environment.put(&class_decl.name, Value::Class(kclass))?;
 
         Ok(Value::Nil)
     }
-    fn visit_break_stmt(&mut self, _break_stmt: &BreakStmt) -> Result<Value> {
+    fn visit_break_stmt(&mut self, break_stmt: &BreakStmt) -> Result<Value> {
         Err(Error::RuntimeError(RuntimeError::new(
-            Token::new(TokenType::BREAK, "break".to_string(), 0, None),
+            Token::new(TokenType::BREAK, "break".to_string(), 0, 0, Self::label_literal(&break_stmt.label)),
             "Break statement encountered.".to_string(),
         )))
     }
-    fn visit_continue_stmt(&mut self, _continue_stmt: &ContinueStmt) -> Result<Value> {
+    fn visit_continue_stmt(&mut self, continue_stmt: &ContinueStmt) -> Result<Value> {
         Err(Error::RuntimeError(RuntimeError::new(
-            Token::new(TokenType::CONTINUE, "continue".to_string(), 0, None),
+            Token::new(TokenType::CONTINUE, "continue".to_string(), 0, 0, Self::label_literal(&continue_stmt.label)),
             "Continue statement encountered.".to_string(),
         )))
     }
+    fn visit_breakpoint_stmt(&mut self, breakpoint_stmt: &BreakpointStmt) -> Result<Value> {
+        if self.interactive {
+            self.debug_prompt(breakpoint_stmt.keyword.line);
+        }
+        Ok(Value::Nil)
+    }
+    fn visit_enum_decl(&mut self, enum_decl: &crate::expr::EnumDecl) -> Result<Value> {
+        if self.at_top_level() {
+            self.check_not_denied(&enum_decl.name)?;
+        }
+        let variants: Vec<&str> = enum_decl.variants.iter().map(|variant| variant.lexeme.as_str()).collect();
+        let lox_enum = LoxEnum::new(&enum_decl.name.lexeme, &variants);
+        self.environment.define(enum_decl.name.lexeme.clone(), Value::Enum(Rc::new(lox_enum)));
+        Ok(Value::Nil)
+    }
     fn visit_function_stmt(&mut self, function_stmt: &FunctionStmt) -> Result<Value> {
+        if self.at_top_level() {
+            self.check_not_denied(&function_stmt.name)?;
+        }
         let func_name = function_stmt.name.lexeme.clone();
         let params = function_stmt.params.iter().map(|param| param.lexeme.clone()).collect();
         let body = (*function_stmt.body).clone();
 
-        let lox_function = LoxFunction::new(
-            func_name.clone(), params, body, Rc::clone(&self.environment), 
-            function_stmt.name.lexeme == "init",
+        let mut lox_function = LoxFunction::new(
+            func_name.clone(), params, body, Rc::clone(&self.environment),
+            // 初期化子になるのはクラスの init メソッドだけ
+            false,
         );
+        lox_function.defaults = Rc::new(function_stmt.defaults.clone());
+        lox_function.is_variadic = function_stmt.is_variadic;
         self.environment.define(
             func_name,
             Value::Callable(crate::callable::LoxCallable::LoxFunction(lox_function)),
@@ -288,6 +1139,42 @@ impl StmtVisitor<Result<Value>> for Interpreter {
         Err(Error::ReturnError(ReturnError::new(Some(value))))
 
     }
+    fn visit_incr_stmt(&mut self, incr_stmt: &crate::expr::IncrStmt) -> Result<Value> {
+        let amount = match &incr_stmt.amount {
+            Some(expr) => self.evaluate(expr)?,
+            None => Value::Number(1.0),
+        };
+        let target = Expr::Variable(incr_stmt.target.clone());
+        let name = &incr_stmt.target.name;
+        let current = self.look_up_variable(name, &target)?;
+        let (Value::Number(current), Value::Number(amount)) = (current, amount) else {
+            return Err(Error::RuntimeError(RuntimeError::new(
+                name.clone(),
+                format!("Operands of '{}' must be numbers.", incr_stmt.keyword.lexeme),
+            )));
+        };
+        let value = match incr_stmt.keyword.type_ {
+            TokenType::DECR => Value::Number(current - amount),
+            _ => Value::Number(current + amount),
+        };
+        if let Some(&(distance, slot)) = self.locals.get(&target) {
+            self.environment.assign_at(distance, slot, name, value)?;
+        } else {
+            self.globals.put(name, value)?;
+        }
+        Ok(Value::Nil)
+    }
+    fn visit_yield_stmt(&mut self, yield_stmt: &crate::expr::YieldStmt) -> Result<Value> {
+        let value = self.evaluate(&yield_stmt.value)?;
+        match &mut self.yielded {
+            Some(values) => values.push(value),
+            None => return Err(Error::RuntimeError(RuntimeError::new(
+                yield_stmt.keyword.clone(),
+                "Can't yield outside a generator.".to_string(),
+            ))),
+        }
+        Ok(Value::Nil)
+    }
 
 }
 
@@ -329,8 +1216,33 @@ impl ExprVisitor<Result<Value>> for Interpreter {
         let object = self.evaluate(&expr.object)?;
         match object {
             Value::Instance(instance) => {
-                instance.borrow().get(&expr.name.lexeme)
+                let value = LoxInstance::get(&instance, &expr.name)?;
+                match value {
+                    // ゲッターは括弧なしで呼び出される
+                    Value::Callable(getter @ LoxCallable::LoxFunction(LoxFunction { is_getter: true, .. })) => {
+                        self.call(&expr.name, Value::Callable(getter), Vec::new())
+                    }
+                    _ => Ok(value),
+                }
             }
+            Value::Class(class) => match class.find_class_method(&expr.name.lexeme) {
+                Some(method) => Ok(method.bind_class(class.clone())),
+                None => Err(Error::RuntimeError(RuntimeError::new(
+                    expr.name.clone(),
+                    format!("Undefined static method '{}'.", expr.name.lexeme),
+                ))),
+            },
+            Value::Enum(lox_enum) => match lox_enum.find_variant(&expr.name.lexeme) {
+                Some(variant) => Ok(Value::EnumVariant(variant)),
+                None => Err(Error::RuntimeError(RuntimeError::new(
+                    expr.name.clone(),
+                    format!("Undefined variant '{}' in enum '{}'.", expr.name.lexeme, lox_enum.name),
+                ))),
+            },
+            Value::Weak(_) => Err(Error::RuntimeError(RuntimeError::new(
+                expr.name.clone(),
+                "Weak references must be dereferenced with 'deref' first.".to_string(),
+            ))),
             _ => Err(Error::RuntimeError(RuntimeError::new(
                 expr.name.clone(),
                 "Only instances have properties.".to_string(),
@@ -344,9 +1256,22 @@ impl ExprVisitor<Result<Value>> for Interpreter {
 
         match object {
             Value::Instance(instance) => {
+                let old = instance.borrow().fields.get(&expr.name.lexeme).cloned().unwrap_or(Value::Nil);
+                let value = match &expr.operator {
+                    Some(operator) => {
+                        let current = LoxInstance::get(&instance, &expr.name)?;
+                        self.apply_binary(operator, current, value)?
+                    }
+                    None => value,
+                };
                 instance.borrow_mut().set(expr.name.lexeme.clone(), value.clone());
+                self.notify_on_set(&instance, &expr.name, old, value.clone())?;
                 Ok(value)
             }
+            Value::Weak(_) => Err(Error::RuntimeError(RuntimeError::new(
+                expr.name.clone(),
+                "Weak references must be dereferenced with 'deref' first.".to_string(),
+            ))),
             _ => Err(Error::RuntimeError(RuntimeError::new(
                 expr.name.clone(),
                 "Only instances have fields.".to_string(),
@@ -357,124 +1282,33 @@ impl ExprVisitor<Result<Value>> for Interpreter {
     fn visit_binary_expr(&mut self, expr: &Binary) -> Result<Value> {
         let left = self.evaluate(&expr.left)?;
         let right = self.evaluate(&expr.right)?;
-
-        match expr.operator.type_ {
-            // Arithmetic operators
-            TokenType::MINUS => {
-                match (left, right) {
-                    (Value::Number(l), Value::Number(r)) => Ok(Value::Number(l - r)),
-                    _ => Err(Error::RuntimeError(RuntimeError::new(
-                        expr.operator.clone(),
-                        "Operands must be numbers.".to_string(),
-                    ))),
-                }
-            }
-            TokenType::SLASH => {
-                match (left, right) {
-                    (Value::Number(l), Value::Number(r)) => {
-                        if r == 0.0 {
-                            Err(Error::RuntimeError(RuntimeError::new(
-                                expr.operator.clone(),
-                                "Division by zero.".to_string(),
-                            )))
-                        } else {
-                            Ok(Value::Number(l / r))
-                        }
-                    }
-                    _ => Err(Error::RuntimeError(RuntimeError::new(
-                        expr.operator.clone(),
-                        "Operands must be numbers.".to_string(),
-                    ))),
-                }
-            }
-            TokenType::STAR => {
-                match (left, right) {
-                    (Value::Number(l), Value::Number(r)) => Ok(Value::Number(l * r)),
-                    _ => Err(Error::RuntimeError(RuntimeError::new(
-                        expr.operator.clone(),
-                        "Operands must be numbers.".to_string(),
-                    ))),
-                }
-            }
-            TokenType::PLUS => {
-                match (left, right) {
-                    (Value::Number(l), Value::Number(r)) => Ok(Value::Number(l + r)),
-                    (Value::String(l), Value::String(r)) => Ok(Value::String(format!("{}{}", l, r))),
-                    (Value::String(l), Value::Number(r)) => Ok(Value::String(format!("{}{}", l, r))),
-                    (Value::Number(l), Value::String(r)) => Ok(Value::String(format!("{}{}", l, r))),
-                    _ => Err(Error::RuntimeError(RuntimeError::new(
-                        expr.operator.clone(),
-                        "Operands must be two numbers or two strings.".to_string(),
-                    ))),
-                }
-            }
-
-            // Comparison operators
-            TokenType::GREATER => {
-                match (left, right) {
-                    (Value::Number(l), Value::Number(r)) => Ok(Value::Bool(l > r)),
-                    _ => Err(Error::RuntimeError(RuntimeError::new(
-                        expr.operator.clone(),
-                        "Operands must be numbers.".to_string(),
-                    ))),
-                }
-            }
-            TokenType::GREATER_EQUAL => {
-                match (left, right) {
-                    (Value::Number(l), Value::Number(r)) => Ok(Value::Bool(l >= r)),
-                    _ => Err(Error::RuntimeError(RuntimeError::new(
-                        expr.operator.clone(),
-                        "Operands must be numbers.".to_string(),
-                    ))),
-                }
-            }
-            TokenType::LESS => {
-                match (left, right) {
-                    (Value::Number(l), Value::Number(r)) => Ok(Value::Bool(l < r)),
-                    _ => Err(Error::RuntimeError(RuntimeError::new(
-                        expr.operator.clone(),
-                        "Operands must be numbers.".to_string(),
-                    ))),
-                }
-            }
-            TokenType::LESS_EQUAL => {
-                match (left, right) {
-                    (Value::Number(l), Value::Number(r)) => Ok(Value::Bool(l <= r)),
-                    _ => Err(Error::RuntimeError(RuntimeError::new(
-                        expr.operator.clone(),
-                        "Operands must be numbers.".to_string(),
-                    ))),
-                }
-            }
-
-            // Equality operators
-            TokenType::BANG_EQUAL => {
-                Ok(Value::Bool(!self.is_equal(&left, &right)))
-            }
-            TokenType::EQUAL_EQUAL => {
-                Ok(Value::Bool(self.is_equal(&left, &right)))
-            }
-
-            _ => Err(Error::RuntimeError(RuntimeError::new(
-                expr.operator.clone(),
-                format!("Unknown binary operator: {:?}", expr.operator.type_),
-            ))),
-        }
+        self.apply_binary(&expr.operator, left, right)
     }
     fn visit_variable_expr(&mut self, expr: &crate::expr::Variable) -> Result<Value> {
-        let name = &expr.name.lexeme;
-        return self.look_up_variable(name, &Expr::Variable(expr.clone()));
+        let variable = Expr::Variable(expr.clone());
+        if !self.locals.contains_key(&variable) {
+            self.check_not_denied(&expr.name)?;
+        }
+        return self.look_up_variable(&expr.name, &variable);
     }
     fn visit_assignment_expr(&mut self, expr: &crate::expr::Assignment) -> Result<Value> {
         let value = self.evaluate(&expr.value)?;
-        let distance = self.locals.get(&Expr::Assignment(expr.clone()));
-        if let Some(distance) = distance {
-            self.environment.assign_at(*distance, &expr.name.lexeme, value.clone())?;
+        if let Some(&(distance, slot)) = self.locals.get(&Expr::Assignment(expr.clone())) {
+            self.environment.assign_at(distance, slot, &expr.name, value.clone())?;
         } else {
-            self.environment.put(&expr.name.lexeme, value.clone())?;
+            self.check_not_denied(&expr.name)?;
+            self.globals.put(&expr.name, value.clone())?;
         }
         Ok(value)  
     }
+    fn visit_coalesce_expr(&mut self, expr: &crate::expr::Coalesce) -> Result<Value> {
+        // or と違い、false はそのまま返す
+        let left = self.evaluate(&expr.left)?;
+        if !matches!(left, Value::Nil) {
+            return Ok(left);
+        }
+        self.evaluate(&expr.right)
+    }
     fn visit_or_expr(&mut self, expr: &crate::expr::OR) -> Result<Value> {
         let left = self.evaluate(&expr.left)?;
         if self.is_truthy(&left) {
@@ -482,6 +1316,31 @@ impl ExprVisitor<Result<Value>> for Interpreter {
         }
         self.evaluate(&expr.right)
     }
+    fn visit_lambda_expr(&mut self, expr: &crate::expr::Lambda) -> Result<Value> {
+        let params = expr.params.iter().map(|param| param.lexeme.clone()).collect();
+        let Some(name) = &expr.name else {
+            let mut lambda = LoxFunction::new(String::new(), params, expr.body.clone(), Rc::clone(&self.environment), false);
+            lambda.defaults = Rc::new(expr.defaults.clone());
+            lambda.is_variadic = expr.is_variadic;
+            return Ok(Value::Callable(crate::callable::LoxCallable::LoxFunction(lambda)));
+        };
+        // The name lives in its own environment around the closure, matching the resolver's scope
+        let env = Rc::new(Environment::new(Some(Rc::clone(&self.environment))));
+        let mut lambda = LoxFunction::new(name.lexeme.clone(), params, expr.body.clone(), Rc::clone(&env), false);
+        lambda.defaults = Rc::new(expr.defaults.clone());
+        lambda.is_variadic = expr.is_variadic;
+        let function = Value::Callable(crate::callable::LoxCallable::LoxFunction(lambda));
+        env.define(name.lexeme.clone(), function.clone());
+        Ok(function)
+    }
+    fn visit_conditional_expr(&mut self, expr: &crate::expr::Conditional) -> Result<Value> {
+        let condition = self.evaluate(&expr.condition)?;
+        if self.is_truthy(&condition) {
+            self.evaluate(&expr.then_branch)
+        } else {
+            self.evaluate(&expr.else_branch)
+        }
+    }
     fn visit_and_expr(&mut self, expr: &crate::expr::AND) -> Result<Value> {
         let left = self.evaluate(&expr.left)?;
         if !self.is_truthy(&left) {
@@ -497,46 +1356,117 @@ impl ExprVisitor<Result<Value>> for Interpreter {
             arguments.push(self.evaluate(argument)?);
         }
 
-        // calleeがCallableかチェック
-        match callee {
-            Value::Callable(function) => {
-                // 引数の数をチェック
-                if arguments.len() != function.arity() {
-                    return Err(Error::RuntimeError(RuntimeError::new(
-                        expr.paren.clone(),
-                        format!(
-                            "Expected {} arguments but got {}.",
-                            function.arity(),
-                            arguments.len()
-                        ),
-                    )));
-                }
-
-                // 関数を呼び出す
-                function.call(arguments, Some(RefCell::new(self.clone())))
+        self.call(&expr.paren, callee, arguments)
+    }
+    fn visit_this_expr(&mut self, expr: &crate::expr::This) -> Result<Value> {
+        self.look_up_variable(&expr.keyword, &Expr::This(expr.clone()))
+    }
+    fn visit_super_expr(&mut self, expr: &crate::expr::Super) -> Result<Value> {
+        let distance = match self.locals.get(&Expr::Super(expr.clone())) {
+            Some(&(distance, _)) => distance,
+            None => return Err(Error::RuntimeError(RuntimeError::new(
+                expr.keyword.clone(),
+                "Can't use 'super' outside of a subclass.".to_string(),
+            ))),
+        };
+        let superclass = match self.environment.get_at(distance, 0, &expr.keyword)? {
+            Value::Class(superclass) => superclass,
+            _ => return Err(Error::RuntimeError(RuntimeError::new(
+                expr.keyword.clone(),
+                "Superclass must be a class.".to_string(),
+            ))),
+        };
+        // "this" は "super" の一つ内側の環境に束縛されている
+        let this = Token::new(TokenType::THIS, "this".to_string(), expr.keyword.line, expr.keyword.column, None);
+        let object = match self.environment.get_at(distance - 1, 0, &this)? {
+            Value::Instance(instance) => instance,
+            _ => return Err(Error::RuntimeError(RuntimeError::new(
+                expr.keyword.clone(),
+                "Can't use 'super' outside of a method.".to_string(),
+            ))),
+        };
+        match superclass.find_method(&expr.method.lexeme) {
+            // ゲッターは this.x と同じく、その場で呼び出す
+            Some(method) if method.is_getter => {
+                self.call_value(method.bind(object), Vec::new())
             }
-            Value::Class(class) => {
-                // クラスのコンストラクタを呼び出す
-                use crate::callable::Callable;
-                if arguments.len() != class.arity() {
-                    return Err(Error::RuntimeError(RuntimeError::new(
-                        expr.paren.clone(),
-                        format!(
-                            "Expected {} arguments but got {}.",
-                            class.arity(),
-                            arguments.len()
-                        ),
-                    )));
+            Some(method) => Ok(method.bind(object)),
+            None => Err(Error::RuntimeError(RuntimeError::new(
+                expr.method.clone(),
+                format!("Undefined property '{}'.", expr.method.lexeme),
+            ))),
+        }
+    }
+    fn visit_interpolation_expr(&mut self, expr: &crate::expr::Interpolation) -> Result<Value> {
+        let mut text = String::new();
+        for part in &expr.parts {
+            let value = self.evaluate(part)?;
+            text.push_str(&self.stringify_mut(&value)?);
+        }
+        Ok(Value::String(text))
+    }
+    fn visit_list_expr(&mut self, expr: &crate::expr::ListExpr) -> Result<Value> {
+        let mut elements = Vec::new();
+        for element in &expr.elements {
+            elements.push(self.evaluate(element)?);
+        }
+        Ok(Value::List(Rc::new(RefCell::new(elements))))
+    }
+    fn visit_map_expr(&mut self, expr: &crate::expr::MapExpr) -> Result<Value> {
+        let mut map = HashMap::new();
+        for (key, value) in &expr.entries {
+            let key = self.evaluate(key)?;
+            let key = Self::map_key(&expr.brace, &key)?;
+            map.insert(key, self.evaluate(value)?);
+        }
+        Ok(Value::Map(Rc::new(RefCell::new(map))))
+    }
+    fn visit_index_expr(&mut self, expr: &crate::expr::Index) -> Result<Value> {
+        let object = self.evaluate(&expr.object)?;
+        let index = self.evaluate(&expr.index)?;
+        match object {
+            Value::List(list) => {
+                let list = list.borrow();
+                let i = Self::list_index(&expr.bracket, &index, list.len())?;
+                Ok(list[i].clone())
+            }
+            Value::Map(map) => {
+                let key = Self::map_key(&expr.bracket, &index)?;
+                match map.borrow().get(&key) {
+                    Some(value) => Ok(value.clone()),
+                    None => Err(Error::RuntimeError(RuntimeError::new(
+                        expr.bracket.clone(),
+                        format!("Undefined key '{}'.", key),
+                    ))),
                 }
-                class.call(arguments, Some(RefCell::new(self.clone())))
             }
             _ => Err(Error::RuntimeError(RuntimeError::new(
-                expr.paren.clone(),
-                "Can only call functions and classes.".to_string(),
+                expr.bracket.clone(),
+                "Only lists and maps can be indexed.".to_string(),
             ))),
         }
     }
-    fn visit_this_expr(&mut self, expr: &crate::expr::This) -> Result<Value> {
-        self.look_up_variable(&expr.keyword.lexeme, &Expr::This(expr.clone()))
+    fn visit_index_set_expr(&mut self, expr: &crate::expr::IndexSet) -> Result<Value> {
+        let object = self.evaluate(&expr.object)?;
+        let index = self.evaluate(&expr.index)?;
+        let value = self.evaluate(&expr.value)?;
+        match object {
+            Value::List(list) => {
+                let mut list = list.borrow_mut();
+                let i = Self::list_index(&expr.bracket, &index, list.len())?;
+                list[i] = value.clone();
+                Ok(value)
+            }
+            // 存在しないキーへの代入は新しいエントリを追加する
+            Value::Map(map) => {
+                let key = Self::map_key(&expr.bracket, &index)?;
+                map.borrow_mut().insert(key, value.clone());
+                Ok(value)
+            }
+            _ => Err(Error::RuntimeError(RuntimeError::new(
+                expr.bracket.clone(),
+                "Only lists and maps can be indexed.".to_string(),
+            ))),
+        }
     }
 }
@@ -0,0 +1,368 @@
+use crate::expr::{AND, Assignment, Binary, Block, BreakStmt, BreakpointStmt, Call, ClassDecl, Coalesce, Conditional, ContinueStmt, DeferStmt, DoWhileStmt, EnumDecl,
+    Expr, ExprVisitor, Expression, ForStmt, FunctionStmt, Get, Grouping, IfStatement, IncrStmt, Index, IndexSet, Interpolation, ListExpr, MapExpr, Lambda,
+    Literal, OR, Print, ReturnStmt, Set, Stmt, StmtVisitor, Super, SwitchStmt, AssertStmt, This, Unary, VarDecl, Variable, WhileStmt, YieldStmt};
+use crate::token::{LiteralType, Token};
+
+/// Serializes the syntax tree as JSON for external tools (`--emit-json`).
+/// Every node is an object with a `type` tag; tokens carry their line and column.
+pub struct JsonPrinter;
+
+impl JsonPrinter {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// A JSON array with one element per top-level statement
+    pub fn print(&mut self, statements: &[Stmt]) -> String {
+        self.statements(statements)
+    }
+
+    /// Helper: `{"type": "<type_>", "<key>": <value>, ...}` (values are already JSON)
+    fn node(type_: &str, fields: &[(&str, String)]) -> String {
+        let mut text = format!("{{\"type\":{}", Self::string(type_));
+        for (key, value) in fields {
+            text.push_str(&format!(",{}:{}", Self::string(key), value));
+        }
+        text.push('}');
+        text
+    }
+
+    fn string(s: &str) -> String {
+        let mut text = String::from("\"");
+        for c in s.chars() {
+            match c {
+                '"' => text.push_str("\\\""),
+                '\\' => text.push_str("\\\\"),
+                '\n' => text.push_str("\\n"),
+                '\r' => text.push_str("\\r"),
+                '\t' => text.push_str("\\t"),
+                c if (c as u32) < 0x20 => text.push_str(&format!("\\u{:04x}", c as u32)),
+                c => text.push(c),
+            }
+        }
+        text.push('"');
+        text
+    }
+
+    fn array(items: Vec<String>) -> String {
+        format!("[{}]", items.join(","))
+    }
+
+    fn token(token: &Token) -> String {
+        format!(
+            "{{\"lexeme\":{},\"line\":{},\"column\":{}}}",
+            Self::string(&token.lexeme),
+            token.line,
+            token.column
+        )
+    }
+
+    fn optional_token(token: Option<&Token>) -> String {
+        token.map_or("null".to_string(), Self::token)
+    }
+
+    fn tokens(tokens: &[Token]) -> String {
+        Self::array(tokens.iter().map(Self::token).collect())
+    }
+
+    fn expr(&mut self, expr: &Expr) -> String {
+        expr.accept(self)
+    }
+
+    fn exprs(&mut self, exprs: &[Expr]) -> String {
+        Self::array(exprs.iter().map(|expr| self.expr(expr)).collect())
+    }
+
+    fn optional_expr(&mut self, expr: Option<&Expr>) -> String {
+        expr.map_or("null".to_string(), |expr| self.expr(expr))
+    }
+
+    fn statements(&mut self, statements: &[Stmt]) -> String {
+        Self::array(statements.iter().map(|stmt| stmt.accept(self)).collect())
+    }
+
+    fn function(&mut self, function: &FunctionStmt) -> String {
+        Self::node("Function", &[
+            ("name", Self::token(&function.name)),
+            ("params", Self::tokens(&function.params)),
+            ("defaults", self.exprs(&function.defaults)),
+            ("isVariadic", function.is_variadic.to_string()),
+            ("body", self.statements(&function.body)),
+            ("isGetter", function.is_getter.to_string()),
+        ])
+    }
+}
+
+impl StmtVisitor<String> for JsonPrinter {
+    fn visit_print_stmt(&mut self, stmt: &Print) -> String {
+        Self::node("Print", &[
+            ("keyword", Self::token(&stmt.keyword)),
+            ("expressions", self.exprs(&stmt.expressions)),
+        ])
+    }
+    fn visit_expression_stmt(&mut self, stmt: &Expression) -> String {
+        Self::node("Expression", &[("expression", self.expr(&stmt.expression))])
+    }
+    fn visit_var_decl(&mut self, var_decl: &VarDecl) -> String {
+        Self::node("Var", &[
+            ("name", Self::token(&var_decl.name)),
+            ("initializer", self.optional_expr(var_decl.initializer.as_ref())),
+            ("unpack", Self::tokens(&var_decl.unpack)),
+        ])
+    }
+    fn visit_block_stmt(&mut self, block: &Block) -> String {
+        Self::node("Block", &[("statements", self.statements(&block.statements))])
+    }
+    fn visit_if_stmt(&mut self, if_stmt: &IfStatement) -> String {
+        Self::node("If", &[
+            ("condition", self.expr(&if_stmt.condition)),
+            ("thenBranch", if_stmt.then_branch.accept(self)),
+            ("elseBranch", if_stmt.else_branch.as_ref().map_or("null".to_string(), |stmt| stmt.accept(self))),
+        ])
+    }
+    fn visit_while_stmt(&mut self, while_stmt: &WhileStmt) -> String {
+        Self::node("While", &[
+            ("condition", self.expr(&while_stmt.condition)),
+            ("body", while_stmt.body.accept(self)),
+            ("label", Self::optional_token(while_stmt.label.as_ref())),
+        ])
+    }
+    fn visit_do_while_stmt(&mut self, do_while_stmt: &DoWhileStmt) -> String {
+        Self::node("DoWhile", &[
+            ("body", do_while_stmt.body.accept(self)),
+            ("condition", self.expr(&do_while_stmt.condition)),
+            ("label", Self::optional_token(do_while_stmt.label.as_ref())),
+        ])
+    }
+    fn visit_for_stmt(&mut self, for_stmt: &ForStmt) -> String {
+        Self::node("For", &[
+            ("initializer", for_stmt.initializer.as_ref().map_or("null".to_string(), |stmt| stmt.accept(self))),
+            ("condition", self.optional_expr(for_stmt.condition.as_deref())),
+            ("increment", self.optional_expr(for_stmt.increment.as_deref())),
+            ("body", for_stmt.body.accept(self)),
+            ("label", Self::optional_token(for_stmt.label.as_ref())),
+        ])
+    }
+    fn visit_break_stmt(&mut self, break_stmt: &BreakStmt) -> String {
+        Self::node("Break", &[
+            ("keyword", Self::token(&break_stmt.keyword)),
+            ("label", Self::optional_token(break_stmt.label.as_ref())),
+        ])
+    }
+    fn visit_continue_stmt(&mut self, continue_stmt: &ContinueStmt) -> String {
+        Self::node("Continue", &[
+            ("keyword", Self::token(&continue_stmt.keyword)),
+            ("label", Self::optional_token(continue_stmt.label.as_ref())),
+        ])
+    }
+    fn visit_breakpoint_stmt(&mut self, breakpoint_stmt: &BreakpointStmt) -> String {
+        Self::node("Breakpoint", &[("keyword", Self::token(&breakpoint_stmt.keyword))])
+    }
+    fn visit_function_stmt(&mut self, function_stmt: &FunctionStmt) -> String {
+        self.function(function_stmt)
+    }
+    fn visit_return_stmt(&mut self, return_stmt: &ReturnStmt) -> String {
+        Self::node("Return", &[
+            ("keyword", Self::token(&return_stmt.keyword)),
+            ("value", self.optional_expr(return_stmt.value.as_deref())),
+        ])
+    }
+    fn visit_yield_stmt(&mut self, yield_stmt: &YieldStmt) -> String {
+        Self::node("Yield", &[
+            ("keyword", Self::token(&yield_stmt.keyword)),
+            ("value", self.expr(&yield_stmt.value)),
+        ])
+    }
+    fn visit_incr_stmt(&mut self, incr_stmt: &IncrStmt) -> String {
+        Self::node("Incr", &[
+            ("keyword", Self::token(&incr_stmt.keyword)),
+            ("target", Self::token(&incr_stmt.target.name)),
+            ("amount", self.optional_expr(incr_stmt.amount.as_deref())),
+        ])
+    }
+    fn visit_class_decl(&mut self, class_decl: &ClassDecl) -> String {
+        let methods = class_decl.methods.iter().map(|method| self.function(method)).collect();
+        let class_methods = class_decl.class_methods.iter().map(|method| self.function(method)).collect();
+        Self::node("Class", &[
+            ("name", Self::token(&class_decl.name)),
+            ("superclass", class_decl.superclass.as_ref().map_or("null".to_string(), |superclass| Self::token(&superclass.name))),
+            ("methods", Self::array(methods)),
+            ("classMethods", Self::array(class_methods)),
+        ])
+    }
+    fn visit_assert_stmt(&mut self, assert_stmt: &AssertStmt) -> String {
+        Self::node("Assert", &[
+            ("keyword", Self::token(&assert_stmt.keyword)),
+            ("condition", self.expr(&assert_stmt.condition)),
+            ("message", self.optional_expr(assert_stmt.message.as_deref())),
+        ])
+    }
+    fn visit_defer_stmt(&mut self, defer_stmt: &DeferStmt) -> String {
+        Self::node("Defer", &[
+            ("keyword", Self::token(&defer_stmt.keyword)),
+            ("body", defer_stmt.body.accept(self)),
+        ])
+    }
+    fn visit_switch_stmt(&mut self, switch_stmt: &SwitchStmt) -> String {
+        let cases = switch_stmt.cases.iter().map(|(value, body)| {
+            format!("{{\"value\":{},\"body\":{}}}", self.expr(value), self.statements(body))
+        }).collect();
+        Self::node("Switch", &[
+            ("keyword", Self::token(&switch_stmt.keyword)),
+            ("discriminant", self.expr(&switch_stmt.discriminant)),
+            ("cases", Self::array(cases)),
+            ("default", switch_stmt.default.as_ref().map_or("null".to_string(), |body| self.statements(body))),
+        ])
+    }
+    fn visit_enum_decl(&mut self, enum_decl: &EnumDecl) -> String {
+        Self::node("Enum", &[
+            ("name", Self::token(&enum_decl.name)),
+            ("variants", Self::tokens(&enum_decl.variants)),
+        ])
+    }
+}
+
+impl ExprVisitor<String> for JsonPrinter {
+    fn visit_binary_expr(&mut self, expr: &Binary) -> String {
+        Self::node("Binary", &[
+            ("left", self.expr(&expr.left)),
+            ("operator", Self::token(&expr.operator)),
+            ("right", self.expr(&expr.right)),
+        ])
+    }
+    fn visit_grouping_expr(&mut self, expr: &Grouping) -> String {
+        Self::node("Grouping", &[("expression", self.expr(&expr.expression))])
+    }
+    fn visit_literal_expr(&mut self, expr: &Literal) -> String {
+        let value = match &expr.value {
+            LiteralType::String(s) => Self::string(s),
+            // JSON has no NaN or Infinity, so those are written as strings
+            LiteralType::Number(n) if !n.is_finite() => Self::string(&n.to_string()),
+            LiteralType::Number(n) => n.to_string(),
+            LiteralType::Bool(b) => b.to_string(),
+            LiteralType::Nil => "null".to_string(),
+        };
+        Self::node("Literal", &[("value", value)])
+    }
+    fn visit_unary_expr(&mut self, expr: &Unary) -> String {
+        Self::node("Unary", &[
+            ("operator", Self::token(&expr.operator)),
+            ("right", self.expr(&expr.right)),
+        ])
+    }
+    fn visit_variable_expr(&mut self, expr: &Variable) -> String {
+        Self::node("Variable", &[("name", Self::token(&expr.name))])
+    }
+    fn visit_assignment_expr(&mut self, expr: &Assignment) -> String {
+        Self::node("Assign", &[
+            ("name", Self::token(&expr.name)),
+            ("value", self.expr(&expr.value)),
+        ])
+    }
+    fn visit_coalesce_expr(&mut self, expr: &Coalesce) -> String {
+        Self::node("Coalesce", &[
+            ("left", self.expr(&expr.left)),
+            ("operator", Self::token(&expr.operator)),
+            ("right", self.expr(&expr.right)),
+        ])
+    }
+    fn visit_or_expr(&mut self, expr: &OR) -> String {
+        Self::node("Logical", &[
+            ("left", self.expr(&expr.left)),
+            ("operator", Self::token(&expr.operator)),
+            ("right", self.expr(&expr.right)),
+        ])
+    }
+    fn visit_and_expr(&mut self, expr: &AND) -> String {
+        Self::node("Logical", &[
+            ("left", self.expr(&expr.left)),
+            ("operator", Self::token(&expr.operator)),
+            ("right", self.expr(&expr.right)),
+        ])
+    }
+    fn visit_call_expr(&mut self, expr: &Call) -> String {
+        let arguments = expr.arguments.iter().map(|argument| self.expr(argument)).collect();
+        Self::node("Call", &[
+            ("callee", self.expr(&expr.callee)),
+            ("paren", Self::token(&expr.paren)),
+            ("arguments", Self::array(arguments)),
+        ])
+    }
+    fn visit_get_expr(&mut self, expr: &Get) -> String {
+        Self::node("Get", &[
+            ("object", self.expr(&expr.object)),
+            ("name", Self::token(&expr.name)),
+        ])
+    }
+    fn visit_set_expr(&mut self, expr: &Set) -> String {
+        Self::node("Set", &[
+            ("object", self.expr(&expr.object)),
+            ("name", Self::token(&expr.name)),
+            ("value", self.expr(&expr.value)),
+            ("operator", expr.operator.as_ref().map_or("null".to_string(), Self::token)),
+        ])
+    }
+    fn visit_this_expr(&mut self, expr: &This) -> String {
+        Self::node("This", &[("keyword", Self::token(&expr.keyword))])
+    }
+    fn visit_super_expr(&mut self, expr: &Super) -> String {
+        Self::node("Super", &[
+            ("keyword", Self::token(&expr.keyword)),
+            ("method", Self::token(&expr.method)),
+        ])
+    }
+    fn visit_conditional_expr(&mut self, expr: &Conditional) -> String {
+        Self::node("Conditional", &[
+            ("condition", self.expr(&expr.condition)),
+            ("thenBranch", self.expr(&expr.then_branch)),
+            ("elseBranch", self.expr(&expr.else_branch)),
+        ])
+    }
+    fn visit_lambda_expr(&mut self, expr: &Lambda) -> String {
+        Self::node("Lambda", &[
+            ("keyword", Self::token(&expr.keyword)),
+            ("name", expr.name.as_ref().map_or("null".to_string(), Self::token)),
+            ("params", Self::tokens(&expr.params)),
+            ("defaults", self.exprs(&expr.defaults)),
+            ("isVariadic", expr.is_variadic.to_string()),
+            ("body", self.statements(&expr.body)),
+        ])
+    }
+    fn visit_list_expr(&mut self, expr: &ListExpr) -> String {
+        let elements = expr.elements.iter().map(|element| self.expr(element)).collect();
+        Self::node("List", &[
+            ("bracket", Self::token(&expr.bracket)),
+            ("elements", Self::array(elements)),
+        ])
+    }
+    fn visit_map_expr(&mut self, expr: &MapExpr) -> String {
+        let entries = expr.entries.iter()
+            .map(|(key, value)| format!("{{\"key\":{},\"value\":{}}}", self.expr(key), self.expr(value)))
+            .collect();
+        Self::node("Map", &[
+            ("brace", Self::token(&expr.brace)),
+            ("entries", Self::array(entries)),
+        ])
+    }
+    fn visit_index_expr(&mut self, expr: &Index) -> String {
+        Self::node("Index", &[
+            ("object", self.expr(&expr.object)),
+            ("bracket", Self::token(&expr.bracket)),
+            ("index", self.expr(&expr.index)),
+        ])
+    }
+    fn visit_interpolation_expr(&mut self, expr: &Interpolation) -> String {
+        Self::node("Interpolation", &[
+            ("start", Self::token(&expr.start)),
+            ("parts", self.exprs(&expr.parts)),
+        ])
+    }
+    fn visit_index_set_expr(&mut self, expr: &IndexSet) -> String {
+        Self::node("IndexSet", &[
+            ("object", self.expr(&expr.object)),
+            ("bracket", Self::token(&expr.bracket)),
+            ("index", self.expr(&expr.index)),
+            ("value", self.expr(&expr.value)),
+        ])
+    }
+}
@@ -7,4 +7,8 @@ pub mod lox;
 pub mod environment;
 pub mod error;
 pub mod callable;
-pub mod resolver;
\ No newline at end of file
+pub mod resolver;
+pub mod optimizer;
+pub mod source_map;
+pub mod ast_printer;
+pub mod json_printer;
\ No newline at end of file
@@ -1,22 +1,47 @@
 use crate::scanner::Scanner;
 use crate::parser::Parser;
 use crate::interpreter::Interpreter;
-use crate::error::RuntimeError;
+use crate::error::{Error, ParseError, RuntimeError};
 use crate::token::Token;
 use crate::resolver::Resolver;
+use crate::optimizer::Optimizer;
+use crate::ast_printer::AstPrinter;
+use crate::json_printer::JsonPrinter;
 use std::rc::Rc;
 use std::cell::RefCell;
 use std::{
     env,
     fs,
     io::{self, BufRead, Write},
-    process,
 };
 
+/// Why `run_file` failed; `main` turns this into an exit code
+#[derive(Debug)]
+pub enum LoxExit {
+    /// The script couldn't be read
+    Io(io::Error),
+    /// Scan, parse or resolve errors; nothing was run
+    CompileError,
+    /// The script started but stopped on a runtime error
+    RuntimeError,
+}
+
+impl From<io::Error> for LoxExit {
+    fn from(error: io::Error) -> Self {
+        LoxExit::Io(error)
+    }
+}
+
 pub struct Lox{
     had_error: bool,
     had_runtime_error: bool,
     interpreter: Rc<RefCell<Interpreter>>,
+    /// Fold constant subexpressions before resolution (`--optimize`)
+    pub optimize: bool,
+    /// Print the syntax tree instead of running it (`--ast`)
+    pub print_ast: bool,
+    /// Print the syntax tree as JSON instead of running it (`--emit-json`)
+    pub emit_json: bool,
 }
 
 impl Lox {
@@ -25,17 +50,20 @@ impl Lox {
             had_error: false,
             had_runtime_error: false,
             interpreter: Rc::new(RefCell::new(Interpreter::new())),
+            optimize: false,
+            print_ast: false,
+            emit_json: false,
         }
     }
 
-    pub fn run_file(&mut self, path: &str) -> io::Result<()> {
+    pub fn run_file(&mut self, path: &str) -> Result<(), LoxExit> {
         let source = fs::read_to_string(path)?;
-        self.run(&source);
+        self.run(&source, false);
         if self.had_error {
-            process::exit(65);
+            return Err(LoxExit::CompileError);
         }
         if self.had_runtime_error {
-            process::exit(70);
+            return Err(LoxExit::RuntimeError);
         }
         Ok(())
     }
@@ -43,6 +71,7 @@ impl Lox {
     pub fn run_prompt(&mut self) -> io::Result<()> {
         let stdin = io::stdin();
         let mut stdout = io::stdout();
+        self.interpreter.borrow_mut().set_interactive(true);
         loop {
             print!("> ");
             stdout.flush()?;
@@ -52,6 +81,10 @@ impl Lox {
             if n == 0 {
                 break;
             }
+            if line.trim_start().starts_with(':') {
+                self.run_command(line.trim());
+                continue;
+            }
             source.push_str(&line);
 
             // Continue reading lines if braces are unbalanced
@@ -66,13 +99,32 @@ impl Lox {
                 source.push_str(&line);
             }
 
-            self.run(&source);
+            self.run(&source, true);
             self.had_error = false;
             self.had_runtime_error = false;
         }
         Ok(())
     }
 
+    /// REPL commands: `:load path` runs a file in the current session and
+    /// `:reset` starts over with a fresh interpreter
+    fn run_command(&mut self, command: &str) {
+        if let Some(path) = command.strip_prefix(":load ") {
+            let path = path.trim();
+            match fs::read_to_string(path) {
+                Ok(source) => self.run(&source, false),
+                Err(e) => eprintln!("Could not read '{}': {}", path, e),
+            }
+        } else if command == ":reset" {
+            self.interpreter = Rc::new(RefCell::new(Interpreter::new()));
+            self.interpreter.borrow_mut().set_interactive(true);
+        } else {
+            eprintln!("Unknown command '{}'. Use ':load <path>' or ':reset'.", command);
+        }
+        self.had_error = false;
+        self.had_runtime_error = false;
+    }
+
     /// Check if braces are balanced in the input
     fn is_balanced(&self, source: &str) -> bool {
         let mut brace_count = 0;
@@ -82,8 +134,10 @@ impl Lox {
         while let Some(ch) = chars.next() {
             if ch == '"' && !in_string {
                 in_string = true;
+            } else if ch == '\\' && in_string {
+                // Skip the escaped character (e.g. \")
+                chars.next();
             } else if ch == '"' && in_string {
-                // Check if it's not an escaped quote
                 in_string = false;
             } else if !in_string {
                 if ch == '{' {
@@ -97,25 +151,87 @@ impl Lox {
         brace_count == 0
     }
 
-    pub fn run(&mut self, source: &str){
+    /// Run `source`. In `repl_mode`, top-level expression statements echo their value.
+    pub fn run(&mut self, source: &str, repl_mode: bool){
         let mut scanner = Scanner::new(source);
-        let tokens: Vec<Token> = scanner.scan_tokens();
+        // 壊れたトークン列を構文解析しても連鎖したエラーが出るだけなので止める
+        let tokens: Vec<Token> = match scanner.try_scan_tokens() {
+            Ok(tokens) => tokens,
+            Err(errors) => {
+                for error in errors {
+                    self.error(error.line, &error.message);
+                }
+                return;
+            }
+        };
 
         let mut parser = Parser::new(tokens);
-        let statements = parser.parse();
+        let mut statements = match parser.parse() {
+            Ok(statements) => statements,
+            Err(errors) => {
+                for error in errors {
+                    self.parse_error(error);
+                }
+                return;
+            }
+        };
 
         if self.had_error {
             return;
         }
-        let mut resolver = Resolver::new(self.interpreter.clone());
+        if self.optimize {
+            statements = Optimizer::new().optimize(&statements);
+        }
+        if self.print_ast {
+            println!("{}", AstPrinter::new().print(&statements));
+            return;
+        }
+        if self.emit_json {
+            println!("{}", JsonPrinter::new().print(&statements));
+            return;
+        }
+        let mut interpreter = self.interpreter.borrow_mut();
+        let mut resolver = Resolver::new(&mut interpreter);
         resolver.resolve_statements(&statements);
-        self.interpreter.borrow_mut().interpret(&statements);
+        for warning in &resolver.warnings {
+            eprintln!("{}", warning);
+        }
+        let resolve_errors = std::mem::take(&mut resolver.errors);
+        if !resolve_errors.is_empty() {
+            drop(interpreter);
+            for error in resolve_errors {
+                self.parse_error(error);
+            }
+            return;
+        }
+        let result = interpreter.interpret(&statements, repl_mode);
+        drop(interpreter);
+        match result {
+            Ok(()) => {}
+            Err(Error::RuntimeError(error)) => self.runtime_error(error),
+            Err(Error::ReturnError(_)) => {
+                // トップレベルの return は関数の外で値を返そうとしたことになる
+                eprintln!("Runtime Error: Can't return from top-level code.");
+                self.had_runtime_error = true;
+            }
+            Err(Error::SyntaxError(messages)) => {
+                for message in messages {
+                    eprintln!("{}", message);
+                }
+                self.had_error = true;
+            }
+        }
     }
 
     pub fn error(&mut self, line: usize, message: &str){
         self.report(line, "", message);
     }
 
+    pub fn parse_error(&mut self, error: ParseError) {
+        eprintln!("{}", error);
+        self.had_error = true;
+    }
+
     fn report(&mut self, line: usize, where_: &str, message: &str){
         eprintln!("[line {}] Error{}: {}", line, where_, message);
         self.had_error = true;
@@ -8,21 +8,59 @@ mod error;
 mod environment;
 mod callable;
 mod resolver;
+mod optimizer;
+mod source_map;
+mod ast_printer;
+mod json_printer;
 use std::env;
 use std::process;
-use crate::lox::Lox;
+use std::thread;
+use crate::lox::{Lox, LoxExit};
+
+/// Stack for the interpreter thread. Each Lox call takes several Rust frames,
+/// so the default main-thread stack can overflow before the interpreter's own
+/// call-depth limit turns runaway recursion into a runtime error.
+const INTERPRETER_STACK_SIZE: usize = 256 * 1024 * 1024;
 
 fn main() {
-    let args: Vec<String> = env::args().skip(1).collect();
+    let interpreter = thread::Builder::new()
+        .stack_size(INTERPRETER_STACK_SIZE)
+        .spawn(run)
+        .expect("failed to start the interpreter thread");
+    if interpreter.join().is_err() {
+        process::exit(70);
+    }
+}
+
+fn run() {
+    let (flags, args): (Vec<String>, Vec<String>) =
+        env::args().skip(1).partition(|arg| arg.starts_with("--"));
     let mut lox = Lox::new();
 
+    for flag in &flags {
+        match flag.as_str() {
+            "--optimize" => lox.optimize = true,
+            "--ast" => lox.print_ast = true,
+            "--emit-json" => lox.emit_json = true,
+            _ => {
+                eprintln!("Usage: rlox [--optimize] [--ast] [--emit-json] [script]");
+                process::exit(64);
+            }
+        }
+    }
+
     if args.len() > 1 {
-        eprintln!("Usage: rlox [script]");
+        eprintln!("Usage: rlox [--optimize] [--ast] [--emit-json] [script]");
         process::exit(64);
     } else if args.len() == 1 {
-        if let Err(e) = lox.run_file(&args[0]) {
-            eprintln!("I/O error: {e}");
-            process::exit(74); // UNIX 的な「I/O エラー」コード
+        match lox.run_file(&args[0]) {
+            Ok(()) => {}
+            Err(LoxExit::Io(e)) => {
+                eprintln!("I/O error: {e}");
+                process::exit(74); // UNIX 的な「I/O エラー」コード
+            }
+            Err(LoxExit::CompileError) => process::exit(65),
+            Err(LoxExit::RuntimeError) => process::exit(70),
         }
     } else {
         if let Err(e) = lox.run_prompt() {
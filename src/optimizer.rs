@@ -0,0 +1,301 @@
+use crate::expr::{AND, Assignment, Binary, Block, BreakStmt, BreakpointStmt, Call, ClassDecl, Coalesce, Conditional, ContinueStmt, DeferStmt, DoWhileStmt, Expr, ExprVisitor,
+    EnumDecl, Expression, ForStmt, FunctionStmt, Get, Grouping, IfStatement, IncrStmt, Index, IndexSet, Interpolation, ListExpr, MapExpr, Lambda, Literal, OR, Print, ReturnStmt, Set, Stmt,
+    StmtVisitor, Super, SwitchStmt, AssertStmt, This, Unary, VarDecl, Variable, WhileStmt, YieldStmt};
+use crate::token::{LiteralType, TokenType};
+use std::rc::Rc;
+
+/// Optimization pass that folds constant subexpressions into literals.
+///
+/// Runs after parsing and before resolution. Only `Binary`, `Unary` and
+/// `Grouping` nodes whose operands are all literals are folded; anything that
+/// would raise a runtime error (e.g. division by zero) is left untouched.
+pub struct Optimizer;
+
+impl Optimizer {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn optimize(&mut self, statements: &Vec<Stmt>) -> Vec<Stmt> {
+        statements.iter().map(|stmt| self.optimize_statement(stmt)).collect()
+    }
+
+    fn optimize_statement(&mut self, stmt: &Stmt) -> Stmt {
+        stmt.accept(self)
+    }
+
+    fn fold(&mut self, expr: &Expr) -> Expr {
+        expr.accept(self)
+    }
+
+    fn fold_box(&mut self, expr: &Expr) -> Box<Expr> {
+        Box::new(self.fold(expr))
+    }
+
+    fn optimize_function(&mut self, function: &FunctionStmt) -> FunctionStmt {
+        FunctionStmt::new(
+            function.name.clone(),
+            function.params.clone(),
+            function.defaults.iter().map(|default| self.fold(default)).collect(),
+            function.is_variadic,
+            Box::new(self.optimize(&function.body)),
+            function.is_getter,
+        )
+    }
+
+    /// Helper: Lox truthiness on a literal (nil and false are falsey)
+    fn is_truthy(literal: &LiteralType) -> bool {
+        match literal {
+            LiteralType::Nil => false,
+            LiteralType::Bool(b) => *b,
+            _ => true,
+        }
+    }
+
+    /// Evaluate a binary operator on two literals, or `None` if it can't be folded.
+    fn fold_binary(operator: &TokenType, left: &LiteralType, right: &LiteralType) -> Option<LiteralType> {
+        use LiteralType::{Bool, Number, String};
+        match (operator, left, right) {
+            (TokenType::MINUS, Number(l), Number(r)) => Some(Number(l - r)),
+            (TokenType::STAR, Number(l), Number(r)) => Some(Number(l * r)),
+            (TokenType::STAR_STAR, Number(l), Number(r)) => Some(Number(l.powf(*r))),
+            // ゼロ除算は実行時エラーを残すため畳み込まない
+            (TokenType::SLASH, Number(l), Number(r)) if *r != 0.0 => Some(Number(l / r)),
+            (TokenType::DIV, Number(l), Number(r)) if *r != 0.0 => Some(Number((l / r).floor())),
+            (TokenType::PLUS, Number(l), Number(r)) => Some(Number(l + r)),
+            (TokenType::PLUS, String(l), String(r)) => Some(String(format!("{}{}", l, r))),
+            (TokenType::PLUS, String(l), Number(r)) => Some(String(format!("{}{}", l, r))),
+            (TokenType::PLUS, Number(l), String(r)) => Some(String(format!("{}{}", l, r))),
+            (TokenType::GREATER, Number(l), Number(r)) => Some(Bool(l > r)),
+            (TokenType::GREATER, String(l), String(r)) => Some(Bool(l > r)),
+            (TokenType::GREATER_EQUAL, Number(l), Number(r)) => Some(Bool(l >= r)),
+            (TokenType::GREATER_EQUAL, String(l), String(r)) => Some(Bool(l >= r)),
+            (TokenType::LESS, Number(l), Number(r)) => Some(Bool(l < r)),
+            (TokenType::LESS, String(l), String(r)) => Some(Bool(l < r)),
+            (TokenType::LESS_EQUAL, Number(l), Number(r)) => Some(Bool(l <= r)),
+            (TokenType::LESS_EQUAL, String(l), String(r)) => Some(Bool(l <= r)),
+            // 数値は実行時と同じく IEEE で比べる (NaN != NaN, 0 == -0)
+            (TokenType::EQUAL_EQUAL, Number(l), Number(r)) => Some(Bool(l == r)),
+            (TokenType::BANG_EQUAL, Number(l), Number(r)) => Some(Bool(l != r)),
+            // 等価比較は型が違っても常に成功する
+            (TokenType::EQUAL_EQUAL, l, r) => Some(Bool(l == r)),
+            (TokenType::BANG_EQUAL, l, r) => Some(Bool(l != r)),
+            _ => None,
+        }
+    }
+}
+
+impl StmtVisitor<Stmt> for Optimizer {
+    fn visit_print_stmt(&mut self, stmt: &Print) -> Stmt {
+        let expressions = stmt.expressions.iter().map(|expression| self.fold(expression)).collect();
+        Stmt::Print(Print::new(stmt.keyword.clone(), expressions))
+    }
+    fn visit_expression_stmt(&mut self, stmt: &Expression) -> Stmt {
+        Stmt::Expression(Expression::new(self.fold_box(&stmt.expression)))
+    }
+    fn visit_var_decl(&mut self, var_decl: &VarDecl) -> Stmt {
+        let initializer = var_decl.initializer.as_ref().map(|expr| self.fold(expr));
+        Stmt::VarDeclaration(VarDecl::new(var_decl.name.clone(), initializer, var_decl.unpack.clone()))
+    }
+    fn visit_block_stmt(&mut self, block: &Block) -> Stmt {
+        Stmt::Block(Block::new(self.optimize(&block.statements)))
+    }
+    fn visit_if_stmt(&mut self, if_stmt: &IfStatement) -> Stmt {
+        Stmt::IfStatement(IfStatement::new(
+            self.fold_box(&if_stmt.condition),
+            Box::new(self.optimize_statement(&if_stmt.then_branch)),
+            if_stmt.else_branch.as_ref().map(|stmt| Box::new(self.optimize_statement(stmt))),
+        ))
+    }
+    fn visit_while_stmt(&mut self, while_stmt: &WhileStmt) -> Stmt {
+        Stmt::WhileStmt(WhileStmt::new(
+            self.fold_box(&while_stmt.condition),
+            Box::new(self.optimize_statement(&while_stmt.body)),
+            while_stmt.label.clone(),
+        ))
+    }
+    fn visit_do_while_stmt(&mut self, do_while_stmt: &DoWhileStmt) -> Stmt {
+        Stmt::DoWhileStmt(DoWhileStmt::new(
+            Box::new(self.optimize_statement(&do_while_stmt.body)),
+            self.fold_box(&do_while_stmt.condition),
+            do_while_stmt.label.clone(),
+        ))
+    }
+    fn visit_for_stmt(&mut self, for_stmt: &ForStmt) -> Stmt {
+        Stmt::ForStmt(ForStmt::new(
+            for_stmt.initializer.as_ref().map(|stmt| Box::new(self.optimize_statement(stmt))),
+            for_stmt.condition.as_ref().map(|expr| self.fold_box(expr)),
+            for_stmt.increment.as_ref().map(|expr| self.fold_box(expr)),
+            Box::new(self.optimize_statement(&for_stmt.body)),
+            for_stmt.label.clone(),
+        ))
+    }
+    fn visit_break_stmt(&mut self, break_stmt: &BreakStmt) -> Stmt {
+        Stmt::BreakStmt(break_stmt.clone())
+    }
+    fn visit_continue_stmt(&mut self, continue_stmt: &ContinueStmt) -> Stmt {
+        Stmt::ContinueStmt(continue_stmt.clone())
+    }
+    fn visit_breakpoint_stmt(&mut self, breakpoint_stmt: &BreakpointStmt) -> Stmt {
+        Stmt::BreakpointStmt(breakpoint_stmt.clone())
+    }
+    fn visit_function_stmt(&mut self, function_stmt: &FunctionStmt) -> Stmt {
+        Stmt::FunctionStmt(self.optimize_function(function_stmt))
+    }
+    fn visit_return_stmt(&mut self, return_stmt: &ReturnStmt) -> Stmt {
+        Stmt::ReturnStmt(ReturnStmt::new(
+            return_stmt.keyword.clone(),
+            return_stmt.value.as_ref().map(|expr| self.fold_box(expr)),
+        ))
+    }
+    fn visit_yield_stmt(&mut self, yield_stmt: &YieldStmt) -> Stmt {
+        Stmt::YieldStmt(YieldStmt::new(yield_stmt.keyword.clone(), self.fold_box(&yield_stmt.value)))
+    }
+    fn visit_incr_stmt(&mut self, incr_stmt: &IncrStmt) -> Stmt {
+        Stmt::IncrStmt(IncrStmt::new(
+            incr_stmt.keyword.clone(),
+            incr_stmt.target.clone(),
+            incr_stmt.amount.as_ref().map(|expr| self.fold_box(expr)),
+        ))
+    }
+    fn visit_class_decl(&mut self, class_decl: &ClassDecl) -> Stmt {
+        let methods = class_decl.methods.iter().map(|method| self.optimize_function(method)).collect();
+        let class_methods = class_decl.class_methods.iter().map(|method| self.optimize_function(method)).collect();
+        Stmt::ClassDecl(ClassDecl::new(class_decl.name.clone(), class_decl.superclass.clone(), methods, class_methods))
+    }
+    fn visit_assert_stmt(&mut self, assert_stmt: &AssertStmt) -> Stmt {
+        Stmt::AssertStmt(AssertStmt::new(
+            assert_stmt.keyword.clone(),
+            self.fold_box(&assert_stmt.condition),
+            assert_stmt.message.as_ref().map(|expr| self.fold_box(expr)),
+        ))
+    }
+    fn visit_defer_stmt(&mut self, defer_stmt: &DeferStmt) -> Stmt {
+        Stmt::DeferStmt(DeferStmt::new(defer_stmt.keyword.clone(), Rc::new(self.optimize_statement(&defer_stmt.body))))
+    }
+    fn visit_switch_stmt(&mut self, switch_stmt: &SwitchStmt) -> Stmt {
+        let cases = switch_stmt.cases.iter().map(|(value, body)| (self.fold(value), self.optimize(body))).collect();
+        Stmt::SwitchStmt(SwitchStmt::new(
+            switch_stmt.keyword.clone(),
+            self.fold_box(&switch_stmt.discriminant),
+            cases,
+            switch_stmt.default.as_ref().map(|body| self.optimize(body)),
+        ))
+    }
+    fn visit_enum_decl(&mut self, enum_decl: &EnumDecl) -> Stmt {
+        Stmt::EnumDecl(enum_decl.clone())
+    }
+}
+
+impl ExprVisitor<Expr> for Optimizer {
+    fn visit_binary_expr(&mut self, expr: &Binary) -> Expr {
+        let left = self.fold(&expr.left);
+        let right = self.fold(&expr.right);
+        if let (Expr::Literal(l), Expr::Literal(r)) = (&left, &right)
+            && let Some(value) = Self::fold_binary(&expr.operator.type_, &l.value, &r.value)
+        {
+            return Expr::Literal(Literal::new(value));
+        }
+        Expr::Binary(Binary::new(Box::new(left), expr.operator.clone(), Box::new(right)))
+    }
+    fn visit_grouping_expr(&mut self, expr: &Grouping) -> Expr {
+        let inner = self.fold(&expr.expression);
+        if let Expr::Literal(_) = inner {
+            return inner;
+        }
+        Expr::Grouping(Grouping::new(Box::new(inner)))
+    }
+    fn visit_literal_expr(&mut self, expr: &Literal) -> Expr {
+        Expr::Literal(expr.clone())
+    }
+    fn visit_unary_expr(&mut self, expr: &Unary) -> Expr {
+        let right = self.fold(&expr.right);
+        if let Expr::Literal(literal) = &right {
+            match (&expr.operator.type_, &literal.value) {
+                (TokenType::MINUS, LiteralType::Number(n)) => {
+                    return Expr::Literal(Literal::new(LiteralType::Number(-n)));
+                }
+                (TokenType::BANG, value) => {
+                    return Expr::Literal(Literal::new(LiteralType::Bool(!Self::is_truthy(value))));
+                }
+                _ => {}
+            }
+        }
+        Expr::Unary(Unary::new(expr.operator.clone(), Box::new(right)))
+    }
+    fn visit_variable_expr(&mut self, expr: &Variable) -> Expr {
+        Expr::Variable(expr.clone())
+    }
+    fn visit_assignment_expr(&mut self, expr: &Assignment) -> Expr {
+        Expr::Assignment(Assignment::new(expr.name.clone(), self.fold_box(&expr.value)))
+    }
+    fn visit_coalesce_expr(&mut self, expr: &Coalesce) -> Expr {
+        Expr::Coalesce(Coalesce::new(self.fold_box(&expr.left), expr.operator.clone(), self.fold_box(&expr.right)))
+    }
+    // 左辺がリテラルなら結果のオペランドが決まる。右辺は副作用があっても
+    // 評価されるときだけ残るので、そのまま返してよい
+    fn visit_or_expr(&mut self, expr: &OR) -> Expr {
+        let left = self.fold(&expr.left);
+        if let Expr::Literal(literal) = &left {
+            return if Self::is_truthy(&literal.value) { left } else { self.fold(&expr.right) };
+        }
+        Expr::OR(OR::new(Box::new(left), expr.operator.clone(), self.fold_box(&expr.right)))
+    }
+    fn visit_and_expr(&mut self, expr: &AND) -> Expr {
+        let left = self.fold(&expr.left);
+        if let Expr::Literal(literal) = &left {
+            return if Self::is_truthy(&literal.value) { self.fold(&expr.right) } else { left };
+        }
+        Expr::AND(AND::new(Box::new(left), expr.operator.clone(), self.fold_box(&expr.right)))
+    }
+    fn visit_lambda_expr(&mut self, expr: &Lambda) -> Expr {
+        let defaults = expr.defaults.iter().map(|default| self.fold(default)).collect();
+        Expr::Lambda(Lambda::new(expr.keyword.clone(), expr.name.clone(), expr.params.clone(), defaults, expr.is_variadic, self.optimize(&expr.body)))
+    }
+    fn visit_conditional_expr(&mut self, expr: &Conditional) -> Expr {
+        Expr::Conditional(Conditional::new(
+            self.fold_box(&expr.condition),
+            self.fold_box(&expr.then_branch),
+            self.fold_box(&expr.else_branch),
+        ))
+    }
+    fn visit_call_expr(&mut self, expr: &Call) -> Expr {
+        let arguments = expr.arguments.iter().map(|argument| self.fold(argument)).collect();
+        Expr::Call(Call::new(self.fold_box(&expr.callee), expr.paren.clone(), arguments))
+    }
+    fn visit_get_expr(&mut self, expr: &Get) -> Expr {
+        Expr::Get(Get::new(self.fold_box(&expr.object), expr.name.clone()))
+    }
+    fn visit_set_expr(&mut self, expr: &Set) -> Expr {
+        Expr::Set(Set::new(self.fold_box(&expr.object), expr.name.clone(), self.fold_box(&expr.value), expr.operator.clone()))
+    }
+    fn visit_list_expr(&mut self, expr: &ListExpr) -> Expr {
+        let elements = expr.elements.iter().map(|element| self.fold(element)).collect();
+        Expr::List(ListExpr::new(expr.bracket.clone(), elements))
+    }
+    fn visit_map_expr(&mut self, expr: &MapExpr) -> Expr {
+        let entries = expr.entries.iter().map(|(key, value)| (self.fold(key), self.fold(value))).collect();
+        Expr::Map(MapExpr::new(expr.brace.clone(), entries))
+    }
+    fn visit_index_expr(&mut self, expr: &Index) -> Expr {
+        Expr::Index(Index::new(self.fold_box(&expr.object), expr.bracket.clone(), self.fold_box(&expr.index)))
+    }
+    fn visit_interpolation_expr(&mut self, expr: &Interpolation) -> Expr {
+        let parts = expr.parts.iter().map(|part| self.fold(part)).collect();
+        Expr::Interpolation(Interpolation::new(expr.start.clone(), parts))
+    }
+    fn visit_index_set_expr(&mut self, expr: &IndexSet) -> Expr {
+        Expr::IndexSet(IndexSet::new(
+            self.fold_box(&expr.object),
+            expr.bracket.clone(),
+            self.fold_box(&expr.index),
+            self.fold_box(&expr.value),
+        ))
+    }
+    fn visit_this_expr(&mut self, expr: &This) -> Expr {
+        Expr::This(expr.clone())
+    }
+    fn visit_super_expr(&mut self, expr: &Super) -> Expr {
+        Expr::Super(expr.clone())
+    }
+}
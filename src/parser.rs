@@ -1,91 +1,217 @@
 use crate::token::{LiteralType, Token, TokenType};
-use crate::expr::{Expr, Literal, Unary, Binary, Grouping, Stmt, BreakStmt, ContinueStmt, ReturnStmt, Set, This, Print,
-    Expression, VarDecl, Variable, Assignment, Block, IfStatement, OR, AND, WhileStmt, ForStmt, Call, ClassDecl};
+use crate::error::ParseError;
+use std::rc::Rc;
+use crate::expr::{Expr, Literal, Unary, Binary, Grouping, Stmt, BreakStmt, ContinueStmt, BreakpointStmt, ReturnStmt, YieldStmt, IncrStmt, Set, This, Super, Print,
+    Expression, VarDecl, Variable, Assignment, Block, IfStatement, OR, AND, WhileStmt, ForStmt, Call, ClassDecl,
+    Conditional, Lambda, FunctionStmt, ListExpr, Index, IndexSet, EnumDecl, SwitchStmt, Interpolation, AssertStmt, Coalesce, DoWhileStmt, MapExpr, DeferStmt};
+
+type ParseResult<T> = std::result::Result<T, ParseError>;
 
 pub struct Parser{
     tokens: Vec<Token>,
     current: usize,
+    errors: Vec<ParseError>,
 }
 impl Parser{
     pub fn new(tokens: Vec<Token>) -> Self{
-        Self {tokens, current: 0}
+        Self {tokens, current: 0, errors: Vec::new()}
     }
-    pub fn parse(&mut self) -> Vec<Stmt>{
+    /// Parse the whole program, collecting every syntax error instead of
+    /// stopping at the first one.
+    pub fn parse(&mut self) -> Result<Vec<Stmt>, Vec<ParseError>>{
         let mut statements = Vec::new();
         while !self.is_at_end(){
-            statements.push(self.declaration());
+            if let Some(stmt) = self.declaration(){
+                statements.push(stmt);
+            }
+        }
+        if self.errors.is_empty(){
+            Ok(statements)
+        } else {
+            Err(std::mem::take(&mut self.errors))
         }
-        statements
     }
-    fn declaration(&mut self) -> Stmt{
+    /// Parse a declaration, recording the error and resynchronizing on failure
+    fn declaration(&mut self) -> Option<Stmt>{
+        match self.try_declaration(){
+            Ok(stmt) => Some(stmt),
+            Err(error) => {
+                self.errors.push(error);
+                self.synchronize();
+                None
+            }
+        }
+    }
+    fn try_declaration(&mut self) -> ParseResult<Stmt>{
         if self.match_token(&[TokenType::VAR]){
             return self.var_declaration();
         }
-        if self.match_token(&[TokenType::FUN]){
+        // 名前が続く場合だけ関数宣言。`fun (` は無名関数の式として扱う
+        if self.check(&TokenType::FUN) && self.check_next(&TokenType::IDENTIFIER){
+            self.advance();
             return self.function_declaration();
         }
         if self.match_token(&[TokenType::CLASS]){
             return self.class_declaration();
         }
-        return self.statement();
+        if self.match_token(&[TokenType::ENUM]){
+            return self.enum_declaration();
+        }
+        self.statement()
     }
-    fn class_declaration(&mut self) -> Stmt {
-        let name = self.consume(TokenType::IDENTIFIER, "Expect class name.").clone();
-        self.consume(TokenType::LEFT_BRACE, "Expect '{' before class body.");
+    fn class_declaration(&mut self) -> ParseResult<Stmt> {
+        let name = self.consume(TokenType::IDENTIFIER, "Expect class name.")?.clone();
+        let superclass = if self.match_token(&[TokenType::LESS]) {
+            let superclass_name = self.consume(TokenType::IDENTIFIER, "Expect superclass name.")?.clone();
+            Some(Variable::new(superclass_name))
+        } else {
+            None
+        };
+        self.consume(TokenType::LEFT_BRACE, "Expect '{' before class body.")?;
         let mut methods: Vec<Stmt> = Vec::new();
+        let mut class_methods: Vec<Stmt> = Vec::new();
         while !self.check(&TokenType::RIGHT_BRACE) && !self.is_at_end() {
-            let method = self.function_declaration();
-            methods.push(method);
-        }
-        self.consume(TokenType::RIGHT_BRACE, "Expect '}' after class body.");
-        return Stmt::ClassDecl(
-            ClassDecl::new(
-                name,
-                methods
-                    .into_iter()
-                    .filter_map(|stmt| if let Stmt::FunctionStmt(func) = stmt { Some(func) } else { None })
-                    .collect(),
-            ),
-        );
-    }
-    fn function_declaration(&mut self) -> Stmt{
-        let name = self.consume(TokenType::IDENTIFIER, "Expect function name.").clone();
-        self.consume(TokenType::LEFT_PAREN, "Expect '(' after function name.");
+            if self.match_token(&[TokenType::CLASS]) {
+                class_methods.push(self.function_declaration()?);
+            } else {
+                methods.push(self.method_declaration()?);
+            }
+        }
+        self.consume(TokenType::RIGHT_BRACE, "Expect '}' after class body.")?;
+        let functions = |stmts: Vec<Stmt>| -> Vec<FunctionStmt> {
+            stmts
+                .into_iter()
+                .filter_map(|stmt| if let Stmt::FunctionStmt(func) = stmt { Some(func) } else { None })
+                .collect()
+        };
+        Ok(Stmt::ClassDecl(
+            ClassDecl::new(name, superclass, functions(methods), functions(class_methods)),
+        ))
+    }
+    fn enum_declaration(&mut self) -> ParseResult<Stmt> {
+        let name = self.consume(TokenType::IDENTIFIER, "Expect enum name.")?.clone();
+        self.consume(TokenType::LEFT_BRACE, "Expect '{' before enum body.")?;
+        let mut variants: Vec<Token> = Vec::new();
+        // 末尾のカンマは許す
+        while !self.check(&TokenType::RIGHT_BRACE) {
+            let variant = self.consume(TokenType::IDENTIFIER, "Expect variant name.")?.clone();
+            if variants.iter().any(|existing| existing.lexeme == variant.lexeme) {
+                self.error(variant.clone(), "Duplicate variant in enum.");
+            }
+            variants.push(variant);
+            if !self.match_token(&[TokenType::COMMA]) {
+                break;
+            }
+        }
+        self.consume(TokenType::RIGHT_BRACE, "Expect '}' after enum body.")?;
+        Ok(Stmt::EnumDecl(EnumDecl::new(name, variants)))
+    }
+    fn function_declaration(&mut self) -> ParseResult<Stmt>{
+        let name = self.consume(TokenType::IDENTIFIER, "Expect function name.")?.clone();
+        self.consume(TokenType::LEFT_PAREN, "Expect '(' after function name.")?;
+        let (parameters, defaults, is_variadic, body) = self.function_body()?;
 
+        Ok(Stmt::FunctionStmt(
+            crate::expr::FunctionStmt::new(name, parameters, defaults, is_variadic, Box::new(body), false)
+        ))
+    }
+    /// Parse a method; one with no '(' after its name is a getter
+    fn method_declaration(&mut self) -> ParseResult<Stmt>{
+        if self.check(&TokenType::IDENTIFIER) && self.check_next(&TokenType::LEFT_BRACE) {
+            let name = self.advance().clone();
+            self.consume(TokenType::LEFT_BRACE, "Expect '{' before getter body.")?;
+            let body = self.block_statement()?;
+            return Ok(Stmt::FunctionStmt(
+                FunctionStmt::new(name, Vec::new(), Vec::new(), false, Box::new(vec![body]), true)
+            ));
+        }
+        self.function_declaration()
+    }
+    /// Parameters (with trailing `name = default`s and an optional final
+    /// `...rest`), whether the last is a rest parameter, and the body, after the opening '('
+    fn function_body(&mut self) -> ParseResult<(Vec<Token>, Vec<Expr>, bool, Vec<Stmt>)>{
         let mut parameters = Vec::new();
+        let mut defaults = Vec::new();
+        let mut is_variadic = false;
+        let mut patterns = Vec::new();
         if !self.check(&TokenType::RIGHT_PAREN){
             loop {
                 if parameters.len() >= 255{
-                    panic!("Can't have more than 255 parameters.");
+                    self.error(self.peek().clone(), "Can't have more than 255 parameters.");
+                }
+                if self.match_token(&[TokenType::ELLIPSIS]){
+                    parameters.push(self.consume(TokenType::IDENTIFIER, "Expect parameter name after '...'.")?.clone());
+                    is_variadic = true;
+                    if !self.match_token(&[TokenType::COMMA]){
+                        break;
+                    }
+                    self.error(self.previous().clone(), "A rest parameter must be the last parameter.");
+                    continue;
+                }
+                let param = if self.match_token(&[TokenType::LEFT_BRACKET]){
+                    let (param, names) = self.parameter_pattern()?;
+                    patterns.push((param.clone(), names));
+                    param
+                } else {
+                    self.consume(TokenType::IDENTIFIER, "Expect parameter name.")?.clone()
+                };
+                if self.match_token(&[TokenType::EQUAL]){
+                    defaults.push(self.assignment()?);
+                } else if !defaults.is_empty() {
+                    self.error(param.clone(), "A parameter without a default can't follow one with a default.");
                 }
-                let param = self.consume(TokenType::IDENTIFIER, "Expect parameter name.").clone();
                 parameters.push(param);
                 if !self.match_token(&[TokenType::COMMA]){
                     break;
                 }
             }
         }
-        self.consume(TokenType::RIGHT_PAREN, "Expect ')' after parameters.");
-
-        self.consume(TokenType::LEFT_BRACE, "Expect '{' before function body.");
-        let body = self.block_statement();
+        self.consume(TokenType::RIGHT_PAREN, "Expect ')' after parameters.")?;
 
-        Stmt::FunctionStmt(
-            crate::expr::FunctionStmt::new(name, parameters, Box::new(vec![body]))
-        )
-    }
-    fn var_declaration(&mut self) -> Stmt{
-        let name = self.consume(TokenType::IDENTIFIER, "Expect variable name.").clone();
+        self.consume(TokenType::LEFT_BRACE, "Expect '{' before function body.")?;
+        let body = self.block_statement()?;
+        // パターン引数は本体の前に `var a, b = [a, b];` として分解する
+        let mut statements: Vec<Stmt> = patterns.into_iter().map(|(param, mut names)| {
+            let first = names.remove(0);
+            Stmt::VarDeclaration(VarDecl::new(first, Some(Expr::Variable(Variable::new(param))), names))
+        }).collect();
+        statements.push(body);
+        Ok((parameters, defaults, is_variadic, statements))
+    }
+    /// A `[a, b, ...]` parameter after its '['. Returns the hidden parameter
+    /// that receives the list (named after the pattern, so it can't clash with
+    /// a real name) and the names to unpack it into.
+    fn parameter_pattern(&mut self) -> ParseResult<(Token, Vec<Token>)>{
+        let bracket = self.previous().clone();
+        let mut names = vec![self.consume(TokenType::IDENTIFIER, "Expect parameter name in pattern.")?.clone()];
+        while self.match_token(&[TokenType::COMMA]){
+            names.push(self.consume(TokenType::IDENTIFIER, "Expect parameter name in pattern.")?.clone());
+        }
+        self.consume(TokenType::RIGHT_BRACKET, "Expect ']' after parameter pattern.")?;
+        if names.len() < 2 {
+            self.error(bracket.clone(), "A parameter pattern needs at least two names.");
+        }
+        let lexeme = format!("[{}]", names.iter().map(|name| name.lexeme.as_str()).collect::<Vec<_>>().join(", "));
+        Ok((Token::new(TokenType::IDENTIFIER, lexeme, bracket.line, bracket.column, None), names))
+    }
+    fn var_declaration(&mut self) -> ParseResult<Stmt>{
+        let name = self.consume(TokenType::IDENTIFIER, "Expect variable name.")?.clone();
+        let mut unpack = Vec::new();
+        while self.match_token(&[TokenType::COMMA]){
+            unpack.push(self.consume(TokenType::IDENTIFIER, "Expect variable name.")?.clone());
+        }
         let mut initializer: Option<Expr> = None;
         if self.match_token(&[TokenType::EQUAL]){
-            initializer = Some(self.expression());
+            initializer = Some(self.expression()?);
+        } else if !unpack.is_empty() {
+            return Err(ParseError::new(self.peek().clone(), "Expect '=' after variable names to unpack.".to_string()));
         }
-        self.consume(TokenType::SEMICOLON, "Expect ';' after variable declaration.");
-        Stmt::VarDeclaration(
-            VarDecl::new(name, initializer)
-        )
+        self.consume(TokenType::SEMICOLON, "Expect ';' after variable declaration.")?;
+        Ok(Stmt::VarDeclaration(
+            VarDecl::new(name, initializer, unpack)
+        ))
     }
-    fn statement(&mut self) -> Stmt{
+    fn statement(&mut self) -> ParseResult<Stmt>{
         if self.match_token(&[TokenType::IF]){
             return self.if_statement()
         }
@@ -95,264 +221,630 @@ impl Parser{
         if self.match_token(&[TokenType::LEFT_BRACE]){
             return self.block_statement();
         }
+        if self.check(&TokenType::IDENTIFIER) && self.check_next(&TokenType::COLON){
+            return self.labeled_statement()
+        }
         if self.match_token(&[TokenType::WHILE]){
-            return self.while_statement()
+            return self.while_statement(None)
+        }
+        if self.match_token(&[TokenType::DO]){
+            return self.do_while_statement(None)
+        }
+        if self.match_token(&[TokenType::SWITCH]){
+            return self.switch_statement()
+        }
+        if self.match_token(&[TokenType::ASSERT]){
+            return self.assert_statement()
+        }
+        if self.match_token(&[TokenType::DEFER]){
+            return self.defer_statement()
         }
         if self.match_token(&[TokenType::FOR]){
-            return self.for_statement()
+            return self.for_statement(None)
         }
         if self.match_token(&[TokenType::RETURN]){
             return self.return_statement()
         }
+        if self.match_token(&[TokenType::YIELD]){
+            return self.yield_statement()
+        }
+        if self.match_token(&[TokenType::INCR, TokenType::DECR]){
+            return self.incr_statement()
+        }
         if self.match_token(&[TokenType::BREAK]){
-            self.consume(TokenType::SEMICOLON, "Expect ';' after 'break'.");
-            return Stmt::BreakStmt(
-                BreakStmt::new()
-            );
+            let keyword = self.previous().clone();
+            let label = self.loop_label()?;
+            self.consume(TokenType::SEMICOLON, "Expect ';' after 'break'.")?;
+            return Ok(Stmt::BreakStmt(
+                BreakStmt::new(keyword, label)
+            ));
         }
         if self.match_token(&[TokenType::CONTINUE]){
-            self.consume(TokenType::SEMICOLON, "Expect ';' after 'continue'.");
-            return Stmt::ContinueStmt(
-                ContinueStmt::new()
-            );
+            let keyword = self.previous().clone();
+            let label = self.loop_label()?;
+            self.consume(TokenType::SEMICOLON, "Expect ';' after 'continue'.")?;
+            return Ok(Stmt::ContinueStmt(
+                ContinueStmt::new(keyword, label)
+            ));
+        }
+        if self.match_token(&[TokenType::BREAKPOINT]){
+            let keyword = self.previous().clone();
+            self.consume(TokenType::SEMICOLON, "Expect ';' after 'breakpoint'.")?;
+            return Ok(Stmt::BreakpointStmt(
+                BreakpointStmt::new(keyword)
+            ));
         }
         self.expression_statement()
     }
-    fn return_statement(&mut self) -> Stmt{
+    fn return_statement(&mut self) -> ParseResult<Stmt>{
         let keyword = self.previous().clone();
         let mut value = None;
         if !self.check(&TokenType::SEMICOLON){
-            value = Some(self.expression());
+            let first = self.expression()?;
+            // `return a, b;` は [a, b] というリストを返す
+            if self.check(&TokenType::COMMA) {
+                let mut elements = vec![first];
+                while self.match_token(&[TokenType::COMMA]){
+                    elements.push(self.expression()?);
+                }
+                value = Some(Expr::List(ListExpr::new(keyword.clone(), elements)));
+            } else {
+                value = Some(first);
+            }
         }
-        self.consume(TokenType::SEMICOLON, "Expect ';' after return value.");
-        return Stmt::ReturnStmt(
+        self.consume(TokenType::SEMICOLON, "Expect ';' after return value.")?;
+        Ok(Stmt::ReturnStmt(
             ReturnStmt::new(keyword, value.map(Box::new))
-        );
+        ))
     }
 
-    fn for_statement(&mut self) -> Stmt{
-        self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'for'.");
+    fn yield_statement(&mut self) -> ParseResult<Stmt>{
+        let keyword = self.previous().clone();
+        let value = self.expression()?;
+        self.consume(TokenType::SEMICOLON, "Expect ';' after yield value.")?;
+        Ok(Stmt::YieldStmt(
+            YieldStmt::new(keyword, Box::new(value))
+        ))
+    }
+
+    fn incr_statement(&mut self) -> ParseResult<Stmt>{
+        let keyword = self.previous().clone();
+        let name = self.consume(TokenType::IDENTIFIER, &format!("Expect variable name after '{}'.", keyword.lexeme))?.clone();
+        let mut amount = None;
+        // "by" は予約語にせず、この位置でだけ特別扱いする
+        if self.check(&TokenType::IDENTIFIER) && self.peek().lexeme == "by" {
+            self.advance();
+            amount = Some(Box::new(self.expression()?));
+        }
+        self.consume(TokenType::SEMICOLON, &format!("Expect ';' after '{}' statement.", keyword.lexeme))?;
+        Ok(Stmt::IncrStmt(
+            IncrStmt::new(keyword, Variable::new(name), amount)
+        ))
+    }
+
+    /// `name: while (...) ...`; only loops can be labeled
+    fn labeled_statement(&mut self) -> ParseResult<Stmt>{
+        let label = self.advance().clone();
+        self.advance();
+        if self.match_token(&[TokenType::WHILE]){
+            return self.while_statement(Some(label))
+        }
+        if self.match_token(&[TokenType::DO]){
+            return self.do_while_statement(Some(label))
+        }
+        if self.match_token(&[TokenType::FOR]){
+            return self.for_statement(Some(label))
+        }
+        Err(ParseError::new(self.peek().clone(), "Expect a loop after label.".to_string()))
+    }
+    /// Optional label after `break`/`continue`
+    fn loop_label(&mut self) -> ParseResult<Option<Token>>{
+        if self.match_token(&[TokenType::IDENTIFIER]){
+            return Ok(Some(self.previous().clone()));
+        }
+        Ok(None)
+    }
+    fn for_statement(&mut self, label: Option<Token>) -> ParseResult<Stmt>{
+        self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'for'.")?;
 
         let initializer = if self.match_token(&[TokenType::SEMICOLON]){
             None
         } else if self.match_token(&[TokenType::VAR]){
-            Some(Box::new(self.var_declaration()))
+            Some(Box::new(self.var_declaration()?))
         } else {
-            Some(Box::new(self.expression_statement()))
+            Some(Box::new(self.expression_statement()?))
         };
 
         let condition = if !self.check(&TokenType::SEMICOLON){
-            Some(Box::new(self.expression()))
+            Some(Box::new(self.expression()?))
         } else {
             None
         };
-        self.consume(TokenType::SEMICOLON, "Expect ';' after loop condition.");
+        self.consume(TokenType::SEMICOLON, "Expect ';' after loop condition.")?;
 
         let increment = if !self.check(&TokenType::RIGHT_PAREN){
-            Some(Box::new(self.expression()))
+            Some(Box::new(self.expression()?))
         } else {
             None
         };
-        self.consume(TokenType::RIGHT_PAREN, "Expect ')' after for clauses.");
+        self.consume(TokenType::RIGHT_PAREN, "Expect ')' after for clauses.")?;
 
-        let body = Box::new(self.statement());
+        let body = Box::new(self.statement()?);
 
-        Stmt::ForStmt(ForStmt::new(initializer, condition, increment, body))
+        Ok(Stmt::ForStmt(ForStmt::new(initializer, condition, increment, body, label)))
+    }
+    fn while_statement(&mut self, label: Option<Token>) -> ParseResult<Stmt>{
+        self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'while'.")?;
+        let condition = self.expression()?;
+        self.consume(TokenType::RIGHT_PAREN, "Expect ')' after condition.")?;
+        let body = Box::new(self.statement()?);
+        Ok(Stmt::WhileStmt(WhileStmt::new(Box::new(condition), body, label)))
+    }
+    fn do_while_statement(&mut self, label: Option<Token>) -> ParseResult<Stmt>{
+        let body = Box::new(self.statement()?);
+        self.consume(TokenType::WHILE, "Expect 'while' after do-while body.")?;
+        self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'while'.")?;
+        let condition = self.expression()?;
+        self.consume(TokenType::RIGHT_PAREN, "Expect ')' after condition.")?;
+        self.consume(TokenType::SEMICOLON, "Expect ';' after do-while condition.")?;
+        Ok(Stmt::DoWhileStmt(DoWhileStmt::new(body, Box::new(condition), label)))
     }
-    fn while_statement(&mut self) -> Stmt{
-        self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'while'.");
-        let condition = self.expression();
-        self.consume(TokenType::RIGHT_PAREN, "Expect ')' after condition.");
-        let body = Box::new(self.statement());
-        Stmt::WhileStmt(WhileStmt::new(Box::new(condition), body))
+
+    fn switch_statement(&mut self) -> ParseResult<Stmt>{
+        let keyword = self.previous().clone();
+        self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'switch'.")?;
+        let discriminant = self.expression()?;
+        self.consume(TokenType::RIGHT_PAREN, "Expect ')' after switch value.")?;
+        self.consume(TokenType::LEFT_BRACE, "Expect '{' before switch body.")?;
+
+        let mut cases = Vec::new();
+        let mut default = None;
+        while !self.check(&TokenType::RIGHT_BRACE) && !self.is_at_end(){
+            if self.match_token(&[TokenType::CASE]){
+                let value = self.expression()?;
+                self.consume(TokenType::COLON, "Expect ':' after case value.")?;
+                cases.push((value, self.switch_arm()));
+            } else if self.match_token(&[TokenType::DEFAULT]){
+                let default_keyword = self.previous().clone();
+                self.consume(TokenType::COLON, "Expect ':' after 'default'.")?;
+                if default.is_some(){
+                    self.error(default_keyword, "A switch can only have one 'default'.");
+                }
+                default = Some(self.switch_arm());
+            } else {
+                return Err(ParseError::new(self.peek().clone(), "Expect 'case' or 'default'.".to_string()));
+            }
+        }
+        self.consume(TokenType::RIGHT_BRACE, "Expect '}' after switch body.")?;
+        Ok(Stmt::SwitchStmt(SwitchStmt::new(keyword, Box::new(discriminant), cases, default)))
+    }
+    /// Statements up to the next `case`, `default` or the end of the switch
+    fn switch_arm(&mut self) -> Vec<Stmt>{
+        let mut statements = Vec::new();
+        while !self.check(&TokenType::CASE) && !self.check(&TokenType::DEFAULT)
+            && !self.check(&TokenType::RIGHT_BRACE) && !self.is_at_end(){
+            if let Some(stmt) = self.declaration(){
+                statements.push(stmt);
+            }
+        }
+        statements
     }
 
-    fn if_statement(&mut self) -> Stmt{
-        self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'if'.");
-        let condition = self.expression();
-        self.consume(TokenType::RIGHT_PAREN, "Expect ')' after if condition.");
+    fn if_statement(&mut self) -> ParseResult<Stmt>{
+        self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'if'.")?;
+        let condition = self.expression()?;
+        self.consume(TokenType::RIGHT_PAREN, "Expect ')' after if condition.")?;
 
-        let then_branch = Box::new(self.statement());
+        let then_branch = Box::new(self.statement()?);
 
         let else_branch = if self.match_token(&[TokenType::ELSE]) {
-            Some(Box::new(self.statement()))
+            Some(Box::new(self.statement()?))
         } else {
             None
         };
 
-        Stmt::IfStatement(IfStatement::new(Box::new(condition), then_branch, else_branch))
+        Ok(Stmt::IfStatement(IfStatement::new(Box::new(condition), then_branch, else_branch)))
     }
-    fn block_statement(&mut self) -> Stmt{
+    fn block_statement(&mut self) -> ParseResult<Stmt>{
         let mut statements = Vec::new();
         while !self.check(&TokenType::RIGHT_BRACE) && !self.is_at_end(){
-            statements.push(self.declaration());
+            if let Some(stmt) = self.declaration(){
+                statements.push(stmt);
+            }
         }
-        self.consume(TokenType::RIGHT_BRACE, "Expect '}' after block.");
-        Stmt::Block(Block::new(statements))
+        self.consume(TokenType::RIGHT_BRACE, "Expect '}' after block.")?;
+        Ok(Stmt::Block(Block::new(statements)))
+    }
+
+    fn defer_statement(&mut self) -> ParseResult<Stmt>{
+        let keyword = self.previous().clone();
+        self.consume(TokenType::LEFT_BRACE, "Expect '{' after 'defer'.")?;
+        let body = self.block_statement()?;
+        Ok(Stmt::DeferStmt(DeferStmt::new(keyword, Rc::new(body))))
     }
 
-    fn print_statement(&mut self) -> Stmt{
-        let value = self.expression();
-        self.consume(TokenType::SEMICOLON, "Expect ';' after value.");
-        Stmt::Print(Print::new(Box::new(value)))
+    fn assert_statement(&mut self) -> ParseResult<Stmt>{
+        let keyword = self.previous().clone();
+        let condition = self.expression()?;
+        let message = if self.match_token(&[TokenType::COMMA]) {
+            Some(Box::new(self.expression()?))
+        } else {
+            None
+        };
+        self.consume(TokenType::SEMICOLON, "Expect ';' after assertion.")?;
+        Ok(Stmt::AssertStmt(AssertStmt::new(keyword, Box::new(condition), message)))
+    }
+
+    fn print_statement(&mut self) -> ParseResult<Stmt>{
+        let keyword = self.previous().clone();
+        let mut values = vec![self.expression()?];
+        while self.match_token(&[TokenType::COMMA]){
+            values.push(self.expression()?);
+        }
+        self.consume(TokenType::SEMICOLON, "Expect ';' after value.")?;
+        Ok(Stmt::Print(Print::new(keyword, values)))
     }
-    fn expression_statement(&mut self) -> Stmt{
-        let expr = self.expression();
-        self.consume(TokenType::SEMICOLON, "Expect ';' after expression.");
-        Stmt::Expression(Expression::new(Box::new(expr)))
+    fn expression_statement(&mut self) -> ParseResult<Stmt>{
+        let expr = self.expression()?;
+        self.consume(TokenType::SEMICOLON, "Expect ';' after expression.")?;
+        Ok(Stmt::Expression(Expression::new(Box::new(expr))))
     }
 
-    pub fn expression(&mut self) -> Expr{
+    pub fn expression(&mut self) -> ParseResult<Expr>{
         self.assignment()
     }
-    pub fn assignment(&mut self) -> Expr{
-        let expr = self.or() ;
+    pub fn assignment(&mut self) -> ParseResult<Expr>{
+        let expr = self.conditional()?;
         if self.match_token(&[TokenType::EQUAL]){
-            let value = self.assignment();
+            let equals = self.previous().clone();
+            let value = self.assignment()?;
             if let Expr::Variable(var_expr) = expr{
                 let name = var_expr.name.clone();
-                return Expr::Assignment(Assignment::new(name, Box::new(value)));
+                return Ok(Expr::Assignment(Assignment::new(name, Box::new(value))));
             }else if let Expr::Get(get_expr) = expr {
                 let name = get_expr.name.clone();
-                return Expr::Set(Set::new(get_expr.object.clone(), name, Box::new(value)));
+                return Ok(Expr::Set(Set::new(get_expr.object.clone(), name, Box::new(value), None)));
+            }else if let Expr::Index(index_expr) = expr {
+                return Ok(Expr::IndexSet(IndexSet::new(index_expr.object, index_expr.bracket, index_expr.index, Box::new(value))));
+            }
+            // 構文は壊れていないので同期せずにエラーだけ記録する
+            self.error(equals, "Invalid assignment target.");
+            return Ok(expr);
+        }
+        if self.match_token(&[TokenType::PLUS_EQUAL, TokenType::MINUS_EQUAL, TokenType::STAR_EQUAL, TokenType::SLASH_EQUAL]){
+            let compound = self.previous().clone();
+            let value = self.assignment()?;
+            // `x op= v` は `x = x op v` に展開する
+            let (type_, lexeme) = match compound.type_ {
+                TokenType::PLUS_EQUAL => (TokenType::PLUS, "+"),
+                TokenType::MINUS_EQUAL => (TokenType::MINUS, "-"),
+                TokenType::STAR_EQUAL => (TokenType::STAR, "*"),
+                _ => (TokenType::SLASH, "/"),
+            };
+            let operator = Token::new(type_, lexeme.to_string(), compound.line, compound.column, None);
+            if let Expr::Variable(var_expr) = &expr {
+                let current = Box::new(expr.clone());
+                let combined = Expr::Binary(Binary::new(current, operator, Box::new(value)));
+                return Ok(Expr::Assignment(Assignment::new(var_expr.name.clone(), Box::new(combined))));
+            } else if let Expr::Get(get_expr) = expr {
+                return Ok(Expr::Set(Set::new(get_expr.object, get_expr.name, Box::new(value), Some(operator))));
             }
-            panic!("Invalid assignment target.");
+            self.error(compound, "Invalid compound assignment target.");
+            return Ok(expr);
+        }
+        Ok(expr)
+    }
+
+    /// condition ? then : else (right-associative, binds looser than `or`)
+    fn conditional(&mut self) -> ParseResult<Expr>{
+        let expr = self.coalesce()?;
+        if self.match_token(&[TokenType::QUESTION]){
+            let then_branch = self.expression()?;
+            self.consume(TokenType::COLON, "Expect ':' after then branch of conditional expression.")?;
+            let else_branch = self.conditional()?;
+            return Ok(Expr::Conditional(Conditional::new(Box::new(expr), Box::new(then_branch), Box::new(else_branch))));
+        }
+        Ok(expr)
+    }
+
+    /// `??` binds more loosely than `or`: `a ?? b or c` is `a ?? (b or c)`
+    fn coalesce(&mut self) -> ParseResult<Expr>{
+        let mut expr = self.or()?;
+        while self.match_token(&[TokenType::QUESTION_QUESTION]){
+            let operator = self.previous().clone();
+            let right = self.or()?;
+            expr = Expr::Coalesce(Coalesce::new(Box::new(expr), operator, Box::new(right)));
         }
-        expr
+        Ok(expr)
     }
 
-    fn or(&mut self) -> Expr{
-        let mut expr = self.and();
+    fn or(&mut self) -> ParseResult<Expr>{
+        let mut expr = self.and()?;
         while self.match_token(&[TokenType::OR]){
             let operator = self.previous().clone();
-            let right = self.and();
+            let right = self.and()?;
             expr = Expr::OR(OR::new(Box::new(expr), operator, Box::new(right)));
         }
-        expr
+        Ok(expr)
     }
 
-    fn and(&mut self) -> Expr{
-        let mut expr = self.equality();
+    fn and(&mut self) -> ParseResult<Expr>{
+        let mut expr = self.equality()?;
         while self.match_token(&[TokenType::AND]){
             let operator = self.previous().clone();
-            let right = self.equality();
+            let right = self.equality()?;
             expr = Expr::AND(AND::new(Box::new(expr), operator, Box::new(right)));
         }
-        expr
+        Ok(expr)
     }
 
-    pub fn equality(&mut self) -> Expr{
-        let mut expr = self.comparison();
+    pub fn equality(&mut self) -> ParseResult<Expr>{
+        let mut expr = self.bit_or()?;
         while self.match_token(&[TokenType::BANG_EQUAL, TokenType::EQUAL_EQUAL]){
             let operator = self.previous().clone();
-            let right = self.comparison();
+            let right = self.bit_or()?;
+            expr = Expr::Binary(Binary::new(Box::new(expr), operator, Box::new(right)));
+        }
+        Ok(expr)
+    }
+    // & | ^ は比較より弱く、等価より強い: `6 & 3 == 2` は `(6 & 3) == 2`
+    fn bit_or(&mut self) -> ParseResult<Expr>{
+        let mut expr = self.bit_xor()?;
+        while self.match_token(&[TokenType::PIPE]){
+            let operator = self.previous().clone();
+            let right = self.bit_xor()?;
+            expr = Expr::Binary(Binary::new(Box::new(expr), operator, Box::new(right)));
+        }
+        Ok(expr)
+    }
+    fn bit_xor(&mut self) -> ParseResult<Expr>{
+        let mut expr = self.bit_and()?;
+        while self.match_token(&[TokenType::CARET]){
+            let operator = self.previous().clone();
+            let right = self.bit_and()?;
             expr = Expr::Binary(Binary::new(Box::new(expr), operator, Box::new(right)));
         }
-        expr
+        Ok(expr)
     }
-    fn comparison(&mut self) -> Expr{
-        let mut expr = self.term();
+    fn bit_and(&mut self) -> ParseResult<Expr>{
+        let mut expr = self.comparison()?;
+        while self.match_token(&[TokenType::AMPERSAND]){
+            let operator = self.previous().clone();
+            let right = self.comparison()?;
+            expr = Expr::Binary(Binary::new(Box::new(expr), operator, Box::new(right)));
+        }
+        Ok(expr)
+    }
+    fn comparison(&mut self) -> ParseResult<Expr>{
+        let mut expr = self.shift()?;
+        let mut compared = false;
         while self.match_token(&[TokenType::GREATER, TokenType::GREATER_EQUAL, TokenType::LESS, TokenType::LESS_EQUAL]){
             let operator = self.previous().clone();
-            let right = self.term();
+            // `1 < 2 < 3` は (1 < 2) < 3 になり紛らわしいので拒否する。括弧で囲めば Grouping なので通る
+            if compared {
+                self.error(operator.clone(), "Chained comparisons are not allowed; use explicit parentheses or `and`.");
+            }
+            compared = true;
+            let right = self.shift()?;
+            expr = Expr::Binary(Binary::new(Box::new(expr), operator, Box::new(right)));
+        }
+        Ok(expr)
+    }
+    // シフトは C と同じく比較より強い: `1 << 2 < 5` は `(1 << 2) < 5`
+    fn shift(&mut self) -> ParseResult<Expr>{
+        let mut expr = self.term()?;
+        while self.match_token(&[TokenType::LESS_LESS, TokenType::GREATER_GREATER]){
+            let operator = self.previous().clone();
+            let right = self.term()?;
             expr = Expr::Binary(Binary::new(Box::new(expr), operator, Box::new(right)));
         }
-        expr
+        Ok(expr)
     }
-    fn term(&mut self) ->  Expr{
-        let mut expr = self.factor();
+    fn term(&mut self) -> ParseResult<Expr>{
+        let mut expr = self.factor()?;
         while self.match_token(&[TokenType::PLUS, TokenType::MINUS]){
             let operator = self.previous().clone();
-            let right = self.factor();
+            let right = self.factor()?;
             expr = Expr::Binary(Binary::new(Box::new(expr), operator, Box::new(right)));
         }
-        expr
+        Ok(expr)
     }
-    fn factor(&mut self) -> Expr{
-        let mut expr = self.unary();
-        while self.match_token(&[TokenType::STAR, TokenType::SLASH]){
+    fn factor(&mut self) -> ParseResult<Expr>{
+        let mut expr = self.power()?;
+        while self.match_token(&[TokenType::STAR, TokenType::SLASH]) || self.match_contextual_div() {
             let operator = self.previous().clone();
-            let right = self.unary();
+            let right = self.power()?;
             expr = Expr::Binary(Binary::new(Box::new(expr), operator, Box::new(right)));
         }
-        expr
+        Ok(expr)
     }
-    fn unary(&mut self) -> Expr{
+    /// `div` is only an operator between two operands; anywhere else it is a
+    /// plain identifier, so programs may still name a variable `div`. The
+    /// consumed token is retyped to `DIV` for the later stages.
+    fn match_contextual_div(&mut self) -> bool {
+        if !self.check(&TokenType::IDENTIFIER) || self.peek().lexeme != "div" {
+            return false;
+        }
+        let current = self.current;
+        self.tokens[current].type_ = TokenType::DIV;
+        self.advance();
+        true
+    }
+    /// `**` is right-associative, so `2 ** 3 ** 2` is `2 ** (3 ** 2)`.
+    fn power(&mut self) -> ParseResult<Expr>{
+        let expr = self.unary()?;
+        if self.match_token(&[TokenType::STAR_STAR]){
+            let operator = self.previous().clone();
+            let right = self.power()?;
+            return Ok(Expr::Binary(Binary::new(Box::new(expr), operator, Box::new(right))));
+        }
+        Ok(expr)
+    }
+    fn unary(&mut self) -> ParseResult<Expr>{
         if self.match_token(&[TokenType::BANG, TokenType::MINUS]){
             let operator = self.previous().clone();
-            let right = self.unary();
-            return Expr::Unary(Unary::new(operator, Box::new(right)));
+            let right = self.unary()?;
+            return Ok(Expr::Unary(Unary::new(operator, Box::new(right))));
         }
         self.call()
     }
-    fn call(&mut self) -> Expr{
-        let mut expr = self.primary();
+    fn call(&mut self) -> ParseResult<Expr>{
+        let mut expr = self.primary()?;
         loop {
             if self.match_token(&[TokenType::LEFT_PAREN]){
-                expr = self.finish_call(expr);
+                expr = self.finish_call(expr)?;
             } else if self.match_token(&[TokenType::DOT]){
-                let name = self.consume(TokenType::IDENTIFIER, "Expect property name after '.'.").clone();
+                let name = self.consume(TokenType::IDENTIFIER, "Expect property name after '.'.")?.clone();
                 expr = Expr::Get(crate::expr::Get::new(Box::new(expr), name));
+            } else if self.match_token(&[TokenType::LEFT_BRACKET]){
+                let index = self.expression()?;
+                let bracket = self.consume(TokenType::RIGHT_BRACKET, "Expect ']' after index.")?.clone();
+                expr = Expr::Index(Index::new(Box::new(expr), bracket, Box::new(index)));
             } else {
                 break;
             }
         }
-        expr
+        Ok(expr)
     }
-    fn finish_call(&mut self, callee: Expr) -> Expr{
+    fn finish_call(&mut self, callee: Expr) -> ParseResult<Expr>{
         let mut arguments = Vec::new();
         if !self.check(&TokenType::RIGHT_PAREN){
             loop {
                 if arguments.len() >= 255{
-                    panic!("Can't have more than 255 arguments.");
+                    self.error(self.peek().clone(), "Can't have more than 255 arguments.");
                 }
-                arguments.push(self.expression());
+                arguments.push(self.expression()?);
                 if !self.match_token(&[TokenType::COMMA]){
                     break;
                 }
             }
         }
-        let paren = self.consume(TokenType::RIGHT_PAREN, "Expect ')' after arguments.").clone();
-        Expr::Call(Call::new(Box::new(callee), paren, arguments))
+        let paren = self.consume(TokenType::RIGHT_PAREN, "Expect ')' after arguments.")?.clone();
+        Ok(Expr::Call(Call::new(Box::new(callee), paren, arguments)))
     }
-    fn primary(&mut self) -> Expr{
+    fn primary(&mut self) -> ParseResult<Expr>{
         if self.match_token(&[TokenType::FALSE]){
-            return Expr::Literal(Literal::new(LiteralType::Bool(false)));
+            return Ok(Expr::Literal(Literal::new(LiteralType::Bool(false))));
         }
         if self.match_token(&[TokenType::TRUE]){
-            return Expr::Literal(Literal::new(LiteralType::Bool(true)));
+            return Ok(Expr::Literal(Literal::new(LiteralType::Bool(true))));
         }
         if self.match_token(&[TokenType::NIL]){
-            return Expr::Literal(Literal::new(LiteralType::Nil));
+            return Ok(Expr::Literal(Literal::new(LiteralType::Nil)));
         }
         if self.match_token(&[TokenType::THIS]){
             let keyword = self.previous().clone();
-            return Expr::This(This::new(keyword));
+            return Ok(Expr::This(This::new(keyword)));
         }
-        if self.match_token(&[TokenType::NUMBER]){
-            let value = match &self.previous().literal{
-                Some(LiteralType::Number(n)) => *n,
-                _ => panic!("Expected number literal"),
+        if self.match_token(&[TokenType::SUPER]){
+            let keyword = self.previous().clone();
+            self.consume(TokenType::DOT, "Expect '.' after 'super'.")?;
+            let method = self.consume(TokenType::IDENTIFIER, "Expect superclass method name.")?.clone();
+            return Ok(Expr::Super(Super::new(keyword, method)));
+        }
+        if self.match_token(&[TokenType::NUMBER, TokenType::STRING]){
+            return match &self.previous().literal{
+                Some(literal) => Ok(Expr::Literal(Literal::new(literal.clone()))),
+                None => Err(ParseError::new(self.previous().clone(), "Expect literal value.".to_string())),
             };
-            return Expr::Literal(Literal::new(LiteralType::Number(value)));
         }
-        if self.match_token(&[TokenType::STRING]){
-            let value = match &self.previous().literal{
-                Some(LiteralType::String(s)) => s.clone(),
-                _ => panic!("Expected string literal"),
+        if self.match_token(&[TokenType::INTERPOLATION]){
+            return self.interpolation();
+        }
+        if self.match_token(&[TokenType::FUN]){
+            let keyword = self.previous().clone();
+            let name = if self.match_token(&[TokenType::IDENTIFIER]) {
+                Some(self.previous().clone())
+            } else {
+                None
             };
-            return Expr::Literal(Literal::new(LiteralType::String(value)));
+            self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'fun'.")?;
+            let (parameters, defaults, is_variadic, body) = self.function_body()?;
+            return Ok(Expr::Lambda(Lambda::new(keyword, name, parameters, defaults, is_variadic, body)));
         }
         if self.match_token(&[TokenType::LEFT_PAREN]){
-            let expr = self.expression();
-            self.consume(TokenType::RIGHT_PAREN, "Expect ')' after expression.");
-            return Expr::Grouping(Grouping::new(Box::new(expr)));
+            let expr = self.expression()?;
+            self.consume(TokenType::RIGHT_PAREN, "Expect ')' after expression.")?;
+            return Ok(Expr::Grouping(Grouping::new(Box::new(expr))));
+        }
+        if self.match_token(&[TokenType::LEFT_BRACKET]){
+            let mut elements = Vec::new();
+            if !self.check(&TokenType::RIGHT_BRACKET){
+                loop {
+                    elements.push(self.expression()?);
+                    if !self.match_token(&[TokenType::COMMA]){
+                        break;
+                    }
+                }
+            }
+            let bracket = self.consume(TokenType::RIGHT_BRACKET, "Expect ']' after list elements.")?.clone();
+            return Ok(Expr::List(ListExpr::new(bracket, elements)));
+        }
+        // 式の位置の '{' はマップ。文頭の '{' は statement() がブロックとして読む
+        if self.match_token(&[TokenType::LEFT_BRACE]){
+            let brace = self.previous().clone();
+            let mut entries = Vec::new();
+            if !self.check(&TokenType::RIGHT_BRACE){
+                loop {
+                    let key = self.expression()?;
+                    self.consume(TokenType::COLON, "Expect ':' after map key.")?;
+                    entries.push((key, self.expression()?));
+                    if !self.match_token(&[TokenType::COMMA]){
+                        break;
+                    }
+                }
+            }
+            self.consume(TokenType::RIGHT_BRACE, "Expect '}' after map entries.")?;
+            return Ok(Expr::Map(MapExpr::new(brace, entries)));
         }
         if self.match_token(&[TokenType::IDENTIFIER]){
             let name = self.previous().clone();
-            return Expr::Variable(Variable::new(name));
+            return Ok(Expr::Variable(Variable::new(name)));
+        }
+        Err(ParseError::new(self.peek().clone(), "Expect expression.".to_string()))
+    }
+
+    /// Record an error that doesn't leave the parser in a confused state
+    fn error(&mut self, token: Token, message: &str){
+        self.errors.push(ParseError::new(token, message.to_string()));
+    }
+
+    /// `"a ${b} c"` after its first `INTERPOLATION` token: string pieces and
+    /// embedded expressions alternate until the closing `STRING` piece
+    fn interpolation(&mut self) -> ParseResult<Expr>{
+        let start = self.previous().clone();
+        let mut parts = Vec::new();
+        loop {
+            if let Some(LiteralType::String(piece)) = &self.previous().literal
+                && !piece.is_empty()
+            {
+                parts.push(Expr::Literal(Literal::new(LiteralType::String(piece.clone()))));
+            }
+            if self.previous().type_ == TokenType::STRING{
+                break;
+            }
+            parts.push(self.expression()?);
+            if !self.match_token(&[TokenType::INTERPOLATION]){
+                self.consume(TokenType::STRING, "Expect '}' after interpolated expression.")?;
+            }
+        }
+        Ok(Expr::Interpolation(Interpolation::new(start, parts)))
+    }
+
+    /// Discard tokens until the start of the next statement
+    fn synchronize(&mut self){
+        self.advance();
+        while !self.is_at_end(){
+            if self.previous().type_ == TokenType::SEMICOLON{
+                return;
+            }
+            match self.peek().type_{
+                TokenType::CLASS | TokenType::ENUM | TokenType::FUN | TokenType::VAR | TokenType::FOR | TokenType::IF
+                | TokenType::WHILE | TokenType::DO | TokenType::SWITCH | TokenType::ASSERT | TokenType::DEFER | TokenType::PRINT | TokenType::RETURN => return,
+                _ => {}
+            }
+            self.advance();
         }
-        panic!("Expected expression.");
     }
 
     fn match_token(&mut self, types: &[TokenType]) -> bool{
@@ -370,6 +862,12 @@ impl Parser{
         }
         &self.peek().type_ == type_
     }
+    fn check_next(&self, type_: &TokenType) -> bool{
+        match self.tokens.get(self.current + 1){
+            Some(token) => &token.type_ == type_,
+            None => false,
+        }
+    }
     fn advance(&mut self) -> &Token{
         if !self.is_at_end(){
             self.current +=1;
@@ -385,10 +883,10 @@ impl Parser{
     fn previous(&self) -> &Token {
         &self.tokens[self.current - 1]
     }
-    fn consume(&mut self, type_: TokenType, message: &str) -> &Token{
+    fn consume(&mut self, type_: TokenType, message: &str) -> ParseResult<&Token>{
         if self.check(&type_){
-            return self.advance();
+            return Ok(self.advance());
         }
-        panic!("{}", message);
+        Err(ParseError::new(self.peek().clone(), message.to_string()))
     }
 }
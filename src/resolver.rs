@@ -1,11 +1,10 @@
-use std::{rc::Rc};
 use crate::token::Token;
 use crate::expr::{Assignment, Expr, ExprVisitor, Expression, FunctionStmt, Stmt, StmtVisitor, VarDecl, Variable
 , ClassDecl};
 
 use crate::{expr::Block, interpreter::Interpreter};
+use crate::error::{ParseError, Warning};
 use std::collections::HashMap;
-use std::cell::RefCell;
 
 #[derive(PartialEq, Eq, Clone, Copy)]
 enum FunctionType{
@@ -18,19 +17,29 @@ enum FunctionType{
 enum ClassType{
     None,
     Class,
+    Subclass,
 }
 
-pub struct Resolver{
-    interpreter: Rc<RefCell<Interpreter>>,
-    scopes: Vec<HashMap<String, bool>>,
+pub struct Resolver<'a>{
+    interpreter: &'a mut Interpreter,
+    /// Each scope maps a name to (is it defined yet, its slot in the environment)
+    scopes: Vec<HashMap<String, (bool, usize)>>,
     current_function: FunctionType,
     current_class: ClassType,
+    /// Number of enclosing loops in the current function, for `break`/`continue`
+    loop_depth: usize,
+    /// Labels of the enclosing loops in the current function, innermost last
+    loop_labels: Vec<String>,
+    /// Errors found so far; the caller reports them and skips running the code
+    pub errors: Vec<ParseError>,
+    /// Lints such as unreachable code; they are reported but the code still runs
+    pub warnings: Vec<Warning>,
 }
 
-impl Resolver {
-    pub fn new(interpreter: Rc<RefCell<Interpreter>>) -> Self {
+impl<'a> Resolver<'a> {
+    pub fn new(interpreter: &'a mut Interpreter) -> Self {
         let scope = HashMap::new();
-        Self { interpreter, scopes: vec![scope], current_function: FunctionType::None, current_class: ClassType::None }
+        Self { interpreter, scopes: vec![scope], current_function: FunctionType::None, current_class: ClassType::None, loop_depth: 0, loop_labels: Vec::new(), errors: Vec::new(), warnings: Vec::new() }
     }
     fn begin_scope(&mut self) {
         self.scopes.push(HashMap::new());
@@ -42,6 +51,24 @@ impl Resolver {
         for statement in statements {
             self.resolve_statement(statement);
         }
+        self.check_unreachable(statements);
+    }
+    /// Warn once about the statements after a `return`/`break`/`continue` in the same list
+    fn check_unreachable(&mut self, statements: &[Stmt]) {
+        // 使えない場所の return/break/continue はすでにエラーになっている
+        let Some(position) = statements.iter().position(|statement| match statement {
+            Stmt::ReturnStmt(_) => self.current_function != FunctionType::None,
+            Stmt::BreakStmt(_) | Stmt::ContinueStmt(_) => self.loop_depth > 0,
+            _ => false,
+        })
+        else {
+            return;
+        };
+        let terminator = &statements[position];
+        if let Some(unreachable) = statements.get(position + 1) {
+            let line = unreachable.line().or(terminator.line()).unwrap_or(0);
+            self.warnings.push(Warning::new(line, "Unreachable code.".to_string()));
+        }
     }
     fn resolve_statement(&mut self, statement: &Stmt) {
         statement.accept(self);
@@ -49,16 +76,27 @@ impl Resolver {
     fn resolve_expression(&mut self, expr: &Expr) {
         expr.accept(self);
     }   
+    fn error(&mut self, token: &Token, message: &str) {
+        self.errors.push(ParseError::new(token.clone(), message.to_string()));
+    }
+    /// Whether the innermost scope is a local one; top-level names are globals,
+    /// which may be redeclared
+    fn in_local_scope(&self) -> bool {
+        self.scopes.len() > 1
+    }
     fn declare(&mut self, name: &Token){
         if self.scopes.is_empty() {
             return;
         }
+        if self.in_local_scope() && self.scopes.last().unwrap().contains_key(&name.lexeme) {
+            self.error(name, "Already a variable with this name in this scope.");
+        }
         let scope = self.scopes.last_mut().unwrap();
         if scope.contains_key(&name.lexeme) {
-            // Variable with this name already declared in this scope
-            panic!("Variable with this name already declared in this scope.");
+            return;
         }
-        scope.insert(name.lexeme.clone(), false);
+        let slot = scope.len();
+        scope.insert(name.lexeme.clone(), (false, slot));
     }
 
     fn define(&mut self, name: &Token){
@@ -66,31 +104,68 @@ impl Resolver {
             return;
         }
         let scope = self.scopes.last_mut().unwrap();
-        scope.insert(name.lexeme.clone(), true);
+        if let Some((is_defined, _)) = scope.get_mut(&name.lexeme) {
+            *is_defined = true;
+        }
     }
     fn resolve_local(&mut self, expr: &Expr, name: &Token){
-        for (i, scope) in self.scopes.iter().rev().enumerate() {
-            if scope.contains_key(&name.lexeme) {
-                self.interpreter.borrow_mut().resolve(expr.clone(),  i);
+        // 最も外側はグローバルで、ネイティブ関数や REPL の前の行の定義もあり
+        // 番号が合わないので解決しない (実行時に名前で探す)
+        let locals = self.scopes.len().saturating_sub(1);
+        for (i, scope) in self.scopes.iter().rev().take(locals).enumerate() {
+            if let Some((_, slot)) = scope.get(&name.lexeme) {
+                self.interpreter.resolve(expr.clone(), i, *slot);
                 return;
             }
         }
     }
     fn resolve_function(&mut self, function: &FunctionStmt, type_: FunctionType){
+        self.resolve_function_body(&function.params, &function.defaults, &function.body, type_);
+    }
+    fn resolve_function_body(&mut self, params: &Vec<Token>, defaults: &Vec<Expr>, body: &Vec<Stmt>, type_: FunctionType){
+        // デフォルト値は関数を定義した環境で評価される
+        for default in defaults {
+            self.resolve_expression(default);
+        }
         let enclosing_function = self.current_function;
+        let enclosing_loop_depth = self.loop_depth;
+        let enclosing_loop_labels = std::mem::take(&mut self.loop_labels);
         self.current_function = type_;
+        // 関数本体から外側のループへは break できない
+        self.loop_depth = 0;
         self.begin_scope();
-        for param in &function.params {
+        for param in params {
             self.declare(param);
             self.define(param);
         }
-        self.resolve_statements(&function.body);
+        self.resolve_statements(body);
         self.end_scope();
         self.current_function = enclosing_function;
+        self.loop_depth = enclosing_loop_depth;
+        self.loop_labels = enclosing_loop_labels;
+    }
+    fn resolve_loop_body(&mut self, label: &Option<Token>, body: &Stmt){
+        self.loop_depth += 1;
+        if let Some(label) = label {
+            self.loop_labels.push(label.lexeme.clone());
+        }
+        self.resolve_statement(body);
+        if label.is_some() {
+            self.loop_labels.pop();
+        }
+        self.loop_depth -= 1;
+    }
+    /// `break name;`/`continue name;` must name an enclosing loop
+    fn check_loop_label(&mut self, label: &Option<Token>){
+        if let Some(label) = label
+            && !self.loop_labels.contains(&label.lexeme)
+        {
+            self.error(label, &format!("No enclosing loop labeled '{}'.", label.lexeme));
+        }
     }
 }
 
-impl StmtVisitor<()> for Resolver {
+impl StmtVisitor<()> for Resolver<'_> {
     fn visit_block_stmt(&mut self, block: &Block) {
         self.begin_scope();
         self.resolve_statements(&block.statements);
@@ -98,10 +173,16 @@ impl StmtVisitor<()> for Resolver {
     }
     fn visit_var_decl(&mut self, var_decl: &VarDecl) -> () {
         self.declare(&var_decl.name);
+        for name in &var_decl.unpack {
+            self.declare(name);
+        }
         if let Some(initializer) = &var_decl.initializer {
             self.resolve_expression(initializer);
         }
         self.define(&var_decl.name);
+        for name in &var_decl.unpack {
+            self.define(name);
+        }
     }
     fn visit_function_stmt(&mut self, function_stmt: &FunctionStmt) -> () {
         self.declare(&function_stmt.name);
@@ -122,30 +203,67 @@ impl StmtVisitor<()> for Resolver {
         return ();
     }
     fn visit_print_stmt(&mut self, stmt: &crate::expr::Print) -> () {
-        self.resolve_expression(&stmt.expression);
+        for expression in &stmt.expressions {
+            self.resolve_expression(expression);
+        }
         return ();
     }
     fn visit_return_stmt(&mut self, return_stmt: &crate::expr::ReturnStmt) -> () {
         if self.current_function == FunctionType::None {
-            panic!("Cannot return from top-level code.");
+            self.error(&return_stmt.keyword, "Can't return from top-level code.");
         }
         if let Some(value) = &return_stmt.value {
             if self.current_function == FunctionType::Initializer {
-                panic!("Cannot return a value from an initializer.");
+                self.error(&return_stmt.keyword, "Can't return a value from an initializer.");
             }
             self.resolve_expression(value);
         }
         return ();
     }
+    fn visit_yield_stmt(&mut self, yield_stmt: &crate::expr::YieldStmt) -> () {
+        if self.current_function == FunctionType::None {
+            self.error(&yield_stmt.keyword, "Can't yield from top-level code.");
+        }
+        if self.current_function == FunctionType::Initializer {
+            self.error(&yield_stmt.keyword, "Can't yield from an initializer.");
+        }
+        self.resolve_expression(&yield_stmt.value);
+        return ();
+    }
+    fn visit_incr_stmt(&mut self, incr_stmt: &crate::expr::IncrStmt) -> () {
+        if let Some(amount) = &incr_stmt.amount {
+            self.resolve_expression(amount);
+        }
+        self.resolve_local(&Expr::Variable(incr_stmt.target.clone()), &incr_stmt.target.name);
+        return ();
+    }
     fn visit_while_stmt(&mut self, while_stmt: &crate::expr::WhileStmt) -> () {
         self.resolve_expression(&while_stmt.condition);
-        self.resolve_statement(&while_stmt.body);
+        self.resolve_loop_body(&while_stmt.label, &while_stmt.body);
+        return ();
+    }
+    fn visit_do_while_stmt(&mut self, do_while_stmt: &crate::expr::DoWhileStmt) -> () {
+        self.resolve_loop_body(&do_while_stmt.label, &do_while_stmt.body);
+        self.resolve_expression(&do_while_stmt.condition);
         return ();
     }
-    fn visit_break_stmt(&mut self, _break_stmt: &crate::expr::BreakStmt) -> () {
+    fn visit_break_stmt(&mut self, break_stmt: &crate::expr::BreakStmt) -> () {
+        if self.loop_depth == 0 {
+            self.error(&break_stmt.keyword, "Can't use 'break' outside of a loop.");
+        } else {
+            self.check_loop_label(&break_stmt.label);
+        }
+        return ();
+    }
+    fn visit_continue_stmt(&mut self, continue_stmt: &crate::expr::ContinueStmt) -> () {
+        if self.loop_depth == 0 {
+            self.error(&continue_stmt.keyword, "Can't use 'continue' outside of a loop.");
+        } else {
+            self.check_loop_label(&continue_stmt.label);
+        }
         return ();
     }
-    fn visit_continue_stmt(&mut self, _continue_stmt: &crate::expr::ContinueStmt) -> () {
+    fn visit_breakpoint_stmt(&mut self, _breakpoint_stmt: &crate::expr::BreakpointStmt) -> () {
         return ();
     }
     fn visit_for_stmt(&mut self, for_stmt: &crate::expr::ForStmt) -> () {
@@ -156,34 +274,80 @@ impl StmtVisitor<()> for Resolver {
         if let Some(condition) = &for_stmt.condition {
             self.resolve_expression(condition);
         }
-        self.resolve_statement(&for_stmt.body);
+        if let Some(increment) = &for_stmt.increment {
+            self.resolve_expression(increment);
+        }
+        self.resolve_loop_body(&for_stmt.label, &for_stmt.body);
         self.end_scope();
     }
     fn visit_class_decl(&mut self, class_decl: &ClassDecl) -> () {
         let enclosing_class = self.current_class;
         self.current_class = ClassType::Class;
         self.declare(&class_decl.name);
+        self.define(&class_decl.name);
+        if let Some(superclass) = &class_decl.superclass {
+            self.current_class = ClassType::Subclass;
+            self.visit_variable_expr(superclass);
+            self.begin_scope();
+            self.scopes.last_mut().unwrap().insert("super".to_string(), (true, 0));
+        }
         self.begin_scope();
-        self.scopes.last_mut().unwrap().insert("this".to_string(), true);
-        for method in &class_decl.methods {
+        self.scopes.last_mut().unwrap().insert("this".to_string(), (true, 0));
+        for method in class_decl.methods.iter().chain(&class_decl.class_methods) {
             let declaration = FunctionType::Method;
             self.resolve_function(method, declaration);
         }
         self.end_scope();
-        self.define(&class_decl.name);
+        if class_decl.superclass.is_some() {
+            self.end_scope();
+        }
         self.current_class = enclosing_class;
         return ();
     }
+    fn visit_assert_stmt(&mut self, assert_stmt: &crate::expr::AssertStmt) -> () {
+        self.resolve_expression(&assert_stmt.condition);
+        if let Some(message) = &assert_stmt.message {
+            self.resolve_expression(message);
+        }
+        return ();
+    }
+    fn visit_defer_stmt(&mut self, defer_stmt: &crate::expr::DeferStmt) -> () {
+        // トップレベルには defer を実行する「ブロックの終わり」がない
+        if !self.in_local_scope() {
+            self.error(&defer_stmt.keyword, "Can't use 'defer' outside of a block.");
+        }
+        self.resolve_statement(&defer_stmt.body);
+        return ();
+    }
+    fn visit_switch_stmt(&mut self, switch_stmt: &crate::expr::SwitchStmt) -> () {
+        self.resolve_expression(&switch_stmt.discriminant);
+        for (value, body) in &switch_stmt.cases {
+            self.resolve_expression(value);
+            // 各アームはブロックと同じく独自のスコープを持つ
+            self.begin_scope();
+            self.resolve_statements(body);
+            self.end_scope();
+        }
+        if let Some(body) = &switch_stmt.default {
+            self.begin_scope();
+            self.resolve_statements(body);
+            self.end_scope();
+        }
+        return ();
+    }
+    fn visit_enum_decl(&mut self, enum_decl: &crate::expr::EnumDecl) -> () {
+        self.declare(&enum_decl.name);
+        self.define(&enum_decl.name);
+        return ();
+    }
 }
-impl ExprVisitor<()> for Resolver {
+impl ExprVisitor<()> for Resolver<'_> {
     fn visit_variable_expr(&mut self, expr: &Variable) -> () {
-        if !self.scopes.is_empty() {
-            if let Some(is_defined) = self.scopes.last().unwrap().get(&expr.name.lexeme) {
-                if !*is_defined {
-                    // Variable is declared but not defined
-                    panic!("Cannot read local variable in its own initializer.");
-                }
-            }
+        if self.in_local_scope()
+            && let Some((false, _)) = self.scopes.last().unwrap().get(&expr.name.lexeme)
+        {
+            // Variable is declared but not defined
+            self.error(&expr.name, "Can't read local variable in its own initializer.");
         }
         self.resolve_local(&Expr::Variable(expr.clone()), &expr.name);
         return ();
@@ -221,11 +385,35 @@ impl ExprVisitor<()> for Resolver {
         self.resolve_expression(&expr.right);
         return ();
     }
+    fn visit_coalesce_expr(&mut self, expr: &crate::expr::Coalesce) -> () {
+        self.resolve_expression(&expr.left);
+        self.resolve_expression(&expr.right);
+        return ();
+    }
     fn visit_or_expr(&mut self, expr: &crate::expr::OR) -> () {
         self.resolve_expression(&expr.left);
         self.resolve_expression(&expr.right);
         return ();
     }
+    fn visit_lambda_expr(&mut self, expr: &crate::expr::Lambda) -> () {
+        // 名前付き関数式の名前は、本体を囲む専用のスコープにだけ束縛する
+        if let Some(name) = &expr.name {
+            self.begin_scope();
+            self.declare(name);
+            self.define(name);
+        }
+        self.resolve_function_body(&expr.params, &expr.defaults, &expr.body, FunctionType::Function);
+        if expr.name.is_some() {
+            self.end_scope();
+        }
+        return ();
+    }
+    fn visit_conditional_expr(&mut self, expr: &crate::expr::Conditional) -> () {
+        self.resolve_expression(&expr.condition);
+        self.resolve_expression(&expr.then_branch);
+        self.resolve_expression(&expr.else_branch);
+        return ();
+    }
     fn visit_get_expr(&mut self, expr: &crate::expr::Get) -> () {
         self.resolve_expression(&expr.object);
         return ();
@@ -237,9 +425,49 @@ impl ExprVisitor<()> for Resolver {
     }
     fn visit_this_expr(&mut self, expr: &crate::expr::This) -> () {
         if self.current_class == ClassType::None {
-            panic!("Cannot use 'this' outside of a class.");
+            self.error(&expr.keyword, "Can't use 'this' outside of a class.");
+            return ();
         }
         self.resolve_local(&Expr::This(expr.clone()), &expr.keyword);
         return ();
     }
-}
\ No newline at end of file
+    fn visit_super_expr(&mut self, expr: &crate::expr::Super) -> () {
+        if self.current_class == ClassType::None {
+            self.error(&expr.keyword, "Can't use 'super' outside of a class.");
+        } else if self.current_class != ClassType::Subclass {
+            self.error(&expr.keyword, "Can't use 'super' in a class with no superclass.");
+        }
+        self.resolve_local(&Expr::Super(expr.clone()), &expr.keyword);
+        return ();
+    }
+    fn visit_interpolation_expr(&mut self, expr: &crate::expr::Interpolation) -> () {
+        for part in &expr.parts {
+            self.resolve_expression(part);
+        }
+        return ();
+    }
+    fn visit_list_expr(&mut self, expr: &crate::expr::ListExpr) -> () {
+        for element in &expr.elements {
+            self.resolve_expression(element);
+        }
+        return ();
+    }
+    fn visit_map_expr(&mut self, expr: &crate::expr::MapExpr) -> () {
+        for (key, value) in &expr.entries {
+            self.resolve_expression(key);
+            self.resolve_expression(value);
+        }
+        return ();
+    }
+    fn visit_index_expr(&mut self, expr: &crate::expr::Index) -> () {
+        self.resolve_expression(&expr.object);
+        self.resolve_expression(&expr.index);
+        return ();
+    }
+    fn visit_index_set_expr(&mut self, expr: &crate::expr::IndexSet) -> () {
+        self.resolve_expression(&expr.object);
+        self.resolve_expression(&expr.index);
+        self.resolve_expression(&expr.value);
+        return ();
+    }
+}
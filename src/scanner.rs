@@ -3,14 +3,23 @@ use crate::token::LiteralType;
 use crate::token::Token;
 use crate::token::TokenType;
 use crate::token::Object;
+use crate::source_map::{line_starts, offset_to_line_col};
+use crate::error::ScanError;
 
 pub struct Scanner {
     pub source: String,
     pub tokens: Vec<Token>,
+    /// Errors found while scanning; `try_scan_tokens` hands them back
+    pub errors: Vec<ScanError>,
+    // 位置はバイトではなく文字単位で数える（マルチバイト文字対策）
+    chars: Vec<char>,
     keywords: std::collections::HashMap<String, TokenType>,
     start : usize,
     current : usize,
-    line : usize,
+    /// Offset of the first character of each line, for token positions
+    line_starts : Vec<usize>,
+    /// Offset of each open `${` and the brace depth inside it, innermost last
+    interpolations : Vec<(usize, usize)>,
 }
     
 impl Scanner {
@@ -19,6 +28,7 @@ impl Scanner {
         keywords.insert("and".to_string(), TokenType::AND);
         keywords.insert("class".to_string(), TokenType::CLASS);
         keywords.insert("else".to_string(), TokenType::ELSE);
+        keywords.insert("enum".to_string(), TokenType::ENUM);
         keywords.insert("false".to_string(), TokenType::FALSE);
         keywords.insert("for".to_string(), TokenType::FOR);
         keywords.insert("fun".to_string(), TokenType::FUN);
@@ -34,28 +44,51 @@ impl Scanner {
         keywords.insert("while".to_string(), TokenType::WHILE);
         keywords.insert("break".to_string(), TokenType::BREAK);
         keywords.insert("continue".to_string(), TokenType::CONTINUE);
+        keywords.insert("breakpoint".to_string(), TokenType::BREAKPOINT);
+        keywords.insert("yield".to_string(), TokenType::YIELD);
+        keywords.insert("incr".to_string(), TokenType::INCR);
+        keywords.insert("decr".to_string(), TokenType::DECR);
+        keywords.insert("switch".to_string(), TokenType::SWITCH);
+        keywords.insert("assert".to_string(), TokenType::ASSERT);
+        keywords.insert("do".to_string(), TokenType::DO);
+        keywords.insert("defer".to_string(), TokenType::DEFER);
+        keywords.insert("case".to_string(), TokenType::CASE);
+        keywords.insert("default".to_string(), TokenType::DEFAULT);
 
         Self {
             source: source.to_string(),
             tokens: Vec::new(),
+            errors: Vec::new(),
+            chars: source.chars().collect(),
             keywords: keywords,
             start: 0,
             current: 0,
-            line: 1,
+            line_starts: line_starts(source),
+            interpolations: Vec::new(),
         }
     }
 
     fn advance(&mut self) -> char{
-        let c = self.source.chars().nth(self.current).unwrap();
+        let c = self.chars[self.current];
         self.current += 1;
         c
     }
+    /// Text between two character positions
+    fn substring(&self, start: usize, end: usize) -> String{
+        self.chars[start..end].iter().collect()
+    }
+    fn error(&mut self, message: &str){
+        let (line, _) = offset_to_line_col(&self.line_starts, self.current);
+        self.errors.push(ScanError::new(line, message.to_string()));
+    }
     fn add_token(&mut self, type_: TokenType, literal: Option<LiteralType>){
-        let text = &self.source[self.start..self.current];
+        let text = self.substring(self.start, self.current);
+        let (line, column) = offset_to_line_col(&self.line_starts, self.start);
         self.tokens.push(Token::new(
             type_,
-            text.to_string(),
-            self.line,
+            text,
+            line,
+            column,
             literal,
         ));
     }
@@ -64,7 +97,7 @@ impl Scanner {
         if self.is_at_end(){
             return false;
         }
-        if self.source.chars().nth(self.current).unwrap() != expected{
+        if self.chars[self.current] != expected{
             return false;
         }
         self.current +=1;
@@ -74,23 +107,48 @@ impl Scanner {
         if self.is_at_end() {
             return '\0';
         }
-        self.source.chars().nth(self.current).unwrap()
+        self.chars[self.current]
     }
 
     fn string(&mut self){
+        let mut value = String::new();
         while self.peek() != '"' && !self.is_at_end(){
-            if self.peek() == '\n'{
-                self.line +=1;
+            let c = self.advance();
+            if c == '$' && self.peek() == '{' {
+                // `${` で文字列を区切り、埋め込み式のトークンを通常通り読む
+                self.advance();
+                self.add_token(TokenType::INTERPOLATION, Some(LiteralType::String(value)));
+                self.interpolations.push((self.current - 2, 0));
+                return;
+            }
+            if c != '\\' {
+                value.push(c);
+                continue;
+            }
+            if self.is_at_end(){
+                break;
+            }
+            match self.advance() {
+                'n' => value.push('\n'),
+                't' => value.push('\t'),
+                'r' => value.push('\r'),
+                '\\' => value.push('\\'),
+                '"' => value.push('"'),
+                '$' => value.push('$'),
+                '0' => value.push('\0'),
+                other => {
+                    self.error(&format!("Unknown escape sequence '\\{}'.", other));
+                }
             }
-            self.advance();
         }
 
         if self.is_at_end(){
-            // エラー処理
+            // 行番号は文字列の開始位置で報告する
+            let (line, _) = offset_to_line_col(&self.line_starts, self.start);
+            self.errors.push(ScanError::new(line, "Unterminated string.".to_string()));
             return;
         }
         self.advance();
-        let value = self.source[self.start + 1..self.current - 1].to_string();
         self.add_token(TokenType::STRING, Some(LiteralType::String(value)));
     }
 
@@ -99,14 +157,74 @@ impl Scanner {
         match c {
             '(' => self.add_token(TokenType::LEFT_PAREN, None),
             ')' => self.add_token(TokenType::RIGHT_PAREN, None),
-            '{' => self.add_token(TokenType::LEFT_BRACE, None),
-            '}' => self.add_token(TokenType::RIGHT_BRACE, None),
+            '{' => {
+                if let Some((_, depth)) = self.interpolations.last_mut(){
+                    *depth += 1;
+                }
+                self.add_token(TokenType::LEFT_BRACE, None);
+            }
+            '}' => {
+                // 埋め込み式を閉じる `}` なら文字列の続きを読む
+                if let Some(&(_, 0)) = self.interpolations.last(){
+                    self.interpolations.pop();
+                    if self.tokens.last().is_some_and(|token| token.type_ == TokenType::INTERPOLATION){
+                        self.error("Expect expression inside '${}'.");
+                    }
+                    self.string();
+                    return;
+                }
+                if let Some((_, depth)) = self.interpolations.last_mut(){
+                    *depth -= 1;
+                }
+                self.add_token(TokenType::RIGHT_BRACE, None);
+            }
+            '[' => self.add_token(TokenType::LEFT_BRACKET, None),
+            ']' => self.add_token(TokenType::RIGHT_BRACKET, None),
             ',' => self.add_token(TokenType::COMMA, None),
-            '.' => self.add_token(TokenType::DOT, None),
-            '-' => self.add_token(TokenType::MINUS, None),
-            '+' => self.add_token(TokenType::PLUS, None),
+            '.' => {
+                if self.peek() == '.' && self.peek_next() == '.'{
+                    self.advance();
+                    self.advance();
+                    self.add_token(TokenType::ELLIPSIS, None);
+                } else {
+                    self.add_token(TokenType::DOT, None);
+                }
+            }
+            '-' => {
+                if self.match_char('='){
+                    self.add_token(TokenType::MINUS_EQUAL, None);
+                } else {
+                    self.add_token(TokenType::MINUS, None);
+                }
+            }
+            '+' => {
+                if self.match_char('='){
+                    self.add_token(TokenType::PLUS_EQUAL, None);
+                } else {
+                    self.add_token(TokenType::PLUS, None);
+                }
+            }
             ';' => self.add_token(TokenType::SEMICOLON, None),
-            '*' => self.add_token(TokenType::STAR, None),
+            '*' => {
+                if self.match_char('='){
+                    self.add_token(TokenType::STAR_EQUAL, None);
+                } else if self.match_char('*'){
+                    self.add_token(TokenType::STAR_STAR, None);
+                } else {
+                    self.add_token(TokenType::STAR, None);
+                }
+            }
+            '?' => {
+                if self.match_char('?'){
+                    self.add_token(TokenType::QUESTION_QUESTION, None);
+                } else {
+                    self.add_token(TokenType::QUESTION, None);
+                }
+            }
+            ':' => self.add_token(TokenType::COLON, None),
+            '&' => self.add_token(TokenType::AMPERSAND, None),
+            '|' => self.add_token(TokenType::PIPE, None),
+            '^' => self.add_token(TokenType::CARET, None),
             '!' => {
                 if self.match_char('='){
                     self.add_token(TokenType::BANG_EQUAL, None);
@@ -124,6 +242,8 @@ impl Scanner {
             '<' => {
                 if self.match_char('='){
                     self.add_token(TokenType::LESS_EQUAL, None);
+                } else if self.match_char('<'){
+                    self.add_token(TokenType::LESS_LESS, None);
                 } else {
                     self.add_token(TokenType::LESS, None);
                 }
@@ -131,6 +251,8 @@ impl Scanner {
             '>' => {
                 if self.match_char('='){
                     self.add_token(TokenType::GREATER_EQUAL, None);
+                } else if self.match_char('>'){
+                    self.add_token(TokenType::GREATER_GREATER, None);
                 } else {
                     self.add_token(TokenType::GREATER, None);
                 }
@@ -140,31 +262,59 @@ impl Scanner {
                     while self.peek() != '\n' && !self.is_at_end(){
                         self.advance();
                     }
+                } else if self.match_char('*'){
+                    self.block_comment();
+                } else if self.match_char('='){
+                    self.add_token(TokenType::SLASH_EQUAL, None);
                 } else {
                     self.add_token(TokenType::SLASH, None);
                 }
             }
-            ' ' | '\r' | '\t' => {},
-            '\n' => {
-                self.line += 1;
-            }
+            ' ' | '\r' | '\t' | '\n' => {},
             '"' => self.string(),
             _ => {
                 if self.is_dight(c) {
                     self.number();
-                } else {
+                } else if self.is_alpha(c) {
                     self.identifier();
+                } else {
+                    self.error("Unexpected character.");
                 }
             },
         }
     }
+    /// Skip a `/* ... */` comment, which may nest
+    fn block_comment(&mut self){
+        let mut depth = 1;
+        while depth > 0 {
+            if self.is_at_end(){
+                self.error("Unterminated block comment.");
+                return;
+            }
+            match self.advance() {
+                '/' if self.peek() == '*' => {
+                    self.advance();
+                    depth += 1;
+                }
+                '*' if self.peek() == '/' => {
+                    self.advance();
+                    depth -= 1;
+                }
+                _ => {}
+            }
+        }
+    }
     fn peek_next(&self) -> char{
-        if self.current + 1 >= self.source.len(){
+        if self.current + 1 >= self.chars.len(){
             return '\0';
         }
-        self.source.chars().nth(self.current + 1).unwrap()
+        self.chars[self.current + 1]
     }
     fn number(&mut self){
+        if self.chars[self.start] == '0' && (self.peek() == 'x' || self.peek() == 'X'){
+            self.hex_number();
+            return;
+        }
         while self.is_dight(self.peek()){
             self.advance();
         }
@@ -174,14 +324,41 @@ impl Scanner {
                 self.advance();
             }
         }
-        self.add_token(
-            TokenType::NUMBER,
-            Some(LiteralType::Number(
-                self.source[self.start..self.current]
-                    .parse()
-                    .unwrap(),
-            )),
-        );
+        // 指数部: e / E の後に符号と数字が続く
+        if self.peek() == 'e' || self.peek() == 'E'{
+            self.advance();
+            if self.peek() == '+' || self.peek() == '-'{
+                self.advance();
+            }
+            if !self.is_dight(self.peek()){
+                self.error("Expect digits in number exponent.");
+                return;
+            }
+            while self.is_dight(self.peek()){
+                self.advance();
+            }
+        }
+        // 不正な数値でもパニックせず、スキャナのエラーとしてトークンを捨てる
+        match self.substring(self.start, self.current).parse(){
+            Ok(value) => self.add_token(TokenType::NUMBER, Some(LiteralType::Number(value))),
+            Err(_) => self.error("Invalid number literal."),
+        }
+    }
+    /// `0x1F`: hex digits after the `0x` prefix, as an integer
+    fn hex_number(&mut self){
+        self.advance();
+        while self.peek().is_ascii_hexdigit(){
+            self.advance();
+        }
+        let digits = self.substring(self.start + 2, self.current);
+        if digits.is_empty(){
+            self.error("Expect hex digits after '0x'.");
+            return;
+        }
+        match i64::from_str_radix(&digits, 16){
+            Ok(value) => self.add_token(TokenType::NUMBER, Some(LiteralType::Number(value as f64))),
+            Err(_) => self.error("Hex literal is too large."),
+        }
     }
     fn is_dight(&mut self,c: char) -> bool {
         c >= '0' && c <= '9'
@@ -197,7 +374,7 @@ impl Scanner {
         while self.is_alphanumeric(self.peek()){
             self.advance();
         }
-        let lexeme = self.source[self.start..self.current].to_string();
+        let lexeme = self.substring(self.start, self.current);
         let type_ = match self.keywords.get(&lexeme){
             Some(t) => t.clone(),
             None => TokenType::IDENTIFIER,
@@ -205,21 +382,40 @@ impl Scanner {
         self.add_token(type_, None);
     }
     
+    /// Scan the whole source, returning the tokens or every error found.
+    /// Tokens are only returned if the source scanned cleanly.
+    pub fn try_scan_tokens(&mut self) -> Result<Vec<Token>, Vec<ScanError>> {
+        let tokens = self.scan_tokens();
+        if self.errors.is_empty() {
+            Ok(tokens)
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+
+    /// Scan the whole source. Errors are collected in `errors`; the tokens
+    /// (ending with EOF) are returned either way.
     pub fn scan_tokens(&mut self) -> Vec<Token> {
         while !self.is_at_end() {
             self.start = self.current;
             self.scan_token();
         }
+        if let Some(&(offset, _)) = self.interpolations.first(){
+            let (line, _) = offset_to_line_col(&self.line_starts, offset);
+            self.errors.push(ScanError::new(line, "Unterminated '${' in string.".to_string()));
+        }
+        let (line, column) = offset_to_line_col(&self.line_starts, self.current);
         self.tokens.push(Token::new(
             TokenType::EOF,
             "".to_string(),
-            self.line,
+            line,
+            column,
             None,
         ));
         self.tokens.clone()
     }
 
     fn is_at_end(&self) -> bool {
-        self.current >= self.source.len()
+        self.current >= self.chars.len()
     }
 }
@@ -0,0 +1,20 @@
+//! Conversions between character offsets in a source string and (line, column)
+//! positions. Offsets count `char`s, not bytes, the same way the scanner does.
+
+/// Offset of the first character of every line; the first entry is always 0.
+/// A trailing newline starts one more (empty) line.
+pub fn line_starts(source: &str) -> Vec<usize> {
+    let mut starts = vec![0];
+    for (offset, c) in source.chars().enumerate() {
+        if c == '\n' {
+            starts.push(offset + 1);
+        }
+    }
+    starts
+}
+
+/// 1-based (line, column) of `offset`, given the result of `line_starts`
+pub fn offset_to_line_col(starts: &[usize], offset: usize) -> (usize, usize) {
+    let line = starts.partition_point(|&start| start <= offset).max(1);
+    (line, offset - starts[line - 1] + 1)
+}
@@ -1,12 +1,13 @@
 use std::cell::RefCell;
 use std::fmt;
+use std::collections::HashMap;
 use std::hash::{Hash, Hasher};
-use crate::callable::{LoxCallable, LoxInstance, LoxClass};
-use std::rc::Rc;
+use crate::callable::{EnumVariant, LoxCallable, LoxEnum, LoxGenerator, LoxInstance, LoxClass};
+use std::rc::{Rc, Weak};
 use crate::lox::Lox;
 
 /// Runtime value type for the interpreter
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone)]
 pub enum Value {
     String(String),
     Number(f64),
@@ -15,18 +16,207 @@ pub enum Value {
     Callable(LoxCallable),
     Instance(Rc<RefCell<LoxInstance>>),
     Class(LoxClass),
+    /// Weak reference to an instance, created by `weak()` and read back with `deref()`
+    Weak(Weak<RefCell<LoxInstance>>),
+    /// Mutable list shared by reference: `[1, 2, 3]`
+    List(Rc<RefCell<Vec<Value>>>),
+    /// Mutable map from string keys, shared by reference: `{"a": 1}`
+    Map(Rc<RefCell<HashMap<String, Value>>>),
+    /// Values produced by calling a generator function, read with `next()`
+    Generator(Rc<RefCell<LoxGenerator>>),
+    /// Namespace created by `enum Name { ... }`; its variants are read as `Name.Variant`
+    Enum(Rc<LoxEnum>),
+    /// One variant of an enum, equal only to itself
+    EnumVariant(Rc<EnumVariant>),
+}
+
+impl Value {
+    /// Every name `type_name` can return
+    pub const TYPE_NAMES: [&'static str; 13] = [
+        "number", "string", "bool", "nil", "function", "class", "instance", "weak", "generator", "list", "map", "enum", "variant",
+    ];
+
+    /// Name of the runtime type, as seen by Lox scripts
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::Number(_) => "number",
+            Value::Bool(_) => "bool",
+            Value::Nil => "nil",
+            Value::Callable(_) => "function",
+            Value::Instance(_) => "instance",
+            Value::Class(_) => "class",
+            Value::Weak(_) => "weak",
+            Value::Generator(_) => "generator",
+            Value::List(_) => "list",
+            Value::Map(_) => "map",
+            Value::Enum(_) => "enum",
+            Value::EnumVariant(_) => "variant",
+        }
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        self.equals(other, &mut Vec::new())
+    }
+}
+
+impl Value {
+    /// `==` on values. `in_progress` holds the addresses of the collection
+    /// pairs being compared further up; meeting one of them again counts as
+    /// equal, so comparing cyclic collections terminates.
+    fn equals(&self, other: &Value, in_progress: &mut Vec<(usize, usize)>) -> bool {
+        match (self, other) {
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Number(a), Value::Number(b)) => a == b,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Nil, Value::Nil) => true,
+            (Value::Callable(a), Value::Callable(b)) => a == b,
+            (Value::Instance(a), Value::Instance(b)) => Rc::ptr_eq(a, b),
+            (Value::Class(a), Value::Class(b)) => a == b,
+            (Value::Weak(a), Value::Weak(b)) => a.ptr_eq(b),
+            (Value::Generator(a), Value::Generator(b)) => Rc::ptr_eq(a, b),
+            (Value::List(a), Value::List(b)) => {
+                if Rc::ptr_eq(a, b) {
+                    return true;
+                }
+                let pair = (Rc::as_ptr(a) as usize, Rc::as_ptr(b) as usize);
+                if in_progress.contains(&pair) {
+                    return true;
+                }
+                let (a, b) = (a.borrow(), b.borrow());
+                if a.len() != b.len() {
+                    return false;
+                }
+                in_progress.push(pair);
+                let equal = a.iter().zip(b.iter()).all(|(x, y)| x.equals(y, in_progress));
+                in_progress.pop();
+                equal
+            }
+            (Value::Map(a), Value::Map(b)) => {
+                if Rc::ptr_eq(a, b) {
+                    return true;
+                }
+                let pair = (Rc::as_ptr(a) as usize, Rc::as_ptr(b) as usize);
+                if in_progress.contains(&pair) {
+                    return true;
+                }
+                let (a, b) = (a.borrow(), b.borrow());
+                if a.len() != b.len() {
+                    return false;
+                }
+                in_progress.push(pair);
+                let equal = a.iter().all(|(key, x)| b.get(key).is_some_and(|y| x.equals(y, in_progress)));
+                in_progress.pop();
+                equal
+            }
+            (Value::Enum(a), Value::Enum(b)) => Rc::ptr_eq(a, b),
+            (Value::EnumVariant(a), Value::EnumVariant(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+/// Format a number the way clox's `printf("%g")` does, but with 15 significant
+/// digits: integers print without decimals, `0.1 + 0.2` prints as `0.3`, very
+/// large or small values use an exponent, and NaN / infinity are `nan` / `inf`.
+pub fn format_number(n: f64) -> String {
+    const PRECISION: i32 = 15;
+    if n.is_nan() {
+        return "nan".to_string();
+    }
+    if n.is_infinite() {
+        return if n > 0.0 { "inf" } else { "-inf" }.to_string();
+    }
+    if n == n.trunc() && n.abs() < 1e15 {
+        return format!("{:.0}", n);
+    }
+    // 丸めた後の指数で固定小数点か指数表記かを決める（%g と同じ規則）
+    let scientific = format!("{:.*e}", (PRECISION - 1) as usize, n);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+    if exponent < -4 || exponent >= PRECISION {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        format!("{}e{}{:02}", trim_fraction(mantissa), sign, exponent.abs())
+    } else {
+        let fixed = format!("{:.*}", (PRECISION - 1 - exponent) as usize, n);
+        trim_fraction(&fixed).to_string()
+    }
+}
+
+/// Helper: drop trailing zeros after the decimal point, and the point itself
+fn trim_fraction(text: &str) -> &str {
+    if text.contains('.') {
+        text.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        text
+    }
 }
 
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_to(f, &mut Vec::new())
+    }
+}
+
+impl Value {
+    /// Display `self`. `open` holds the addresses of the collections being
+    /// printed further up, so a list inside itself prints as `[...]` and a
+    /// map as `{...}`.
+    fn write_to(&self, f: &mut fmt::Formatter, open: &mut Vec<usize>) -> fmt::Result {
         match self {
             Value::String(s) => write!(f, "{}", s),
-            Value::Number(n) => write!(f, "{}", n),
+            Value::Number(n) => write!(f, "{}", format_number(*n)),
             Value::Bool(b) => write!(f, "{}", b),
             Value::Nil => write!(f, "nil"),
             Value::Callable(callable) => write!(f, "<fn {}>", callable.name()),
             Value::Instance(instance) => write!(f, "<instance of {}>", instance.borrow().class.name),
             Value::Class(class) => write!(f, "<class {}>", class.name),
+            Value::Weak(weak) => match weak.upgrade() {
+                Some(instance) => write!(f, "<weak instance of {}>", instance.borrow().class.name),
+                None => write!(f, "<weak (collected)>"),
+            },
+            Value::Generator(_) => write!(f, "<generator>"),
+            Value::Enum(lox_enum) => write!(f, "<enum {}>", lox_enum.name),
+            Value::EnumVariant(variant) => write!(f, "{}.{}", variant.enum_name, variant.name),
+            Value::List(list) => {
+                let address = Rc::as_ptr(list) as usize;
+                if open.contains(&address) {
+                    return write!(f, "[...]");
+                }
+                open.push(address);
+                write!(f, "[")?;
+                for (i, value) in list.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    value.write_to(f, open)?;
+                }
+                open.pop();
+                write!(f, "]")
+            }
+            Value::Map(map) => {
+                let address = Rc::as_ptr(map) as usize;
+                if open.contains(&address) {
+                    return write!(f, "{{...}}");
+                }
+                open.push(address);
+                // HashMap の順序は不定なので、キーの順に並べて表示する
+                let map = map.borrow();
+                let mut keys: Vec<&String> = map.keys().collect();
+                keys.sort();
+                write!(f, "{{")?;
+                for (i, key) in keys.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: ", key)?;
+                    map[*key].write_to(f, open)?;
+                }
+                open.pop();
+                write!(f, "}}")
+            }
         }
     }
 }
@@ -38,6 +228,8 @@ pub enum TokenType {
     RIGHT_PAREN,
     LEFT_BRACE,
     RIGHT_BRACE,
+    LEFT_BRACKET,
+    RIGHT_BRACKET,
     COMMA,
     DOT,
     MINUS,
@@ -45,6 +237,19 @@ pub enum TokenType {
     SEMICOLON,
     SLASH,
     STAR,
+    /// `**` exponentiation
+    STAR_STAR,
+    /// Compound assignment: `+=`, `-=`, `*=`, `/=`
+    PLUS_EQUAL,
+    MINUS_EQUAL,
+    STAR_EQUAL,
+    SLASH_EQUAL,
+    /// `...` before a rest parameter
+    ELLIPSIS,
+    QUESTION,
+    /// Nil-coalescing `a ?? b`
+    QUESTION_QUESTION,
+    COLON,
     // ... other token types ...
     BANG,
     BANG_EQUAL,
@@ -54,16 +259,45 @@ pub enum TokenType {
     GREATER_EQUAL,
     LESS,
     LESS_EQUAL,
+    /// Bitwise operators on integer-valued numbers: `&`, `|`, `^`, `<<`, `>>`
+    AMPERSAND,
+    PIPE,
+    CARET,
+    LESS_LESS,
+    GREATER_GREATER,
     // literal
     IDENTIFIER,
     STRING,
+    /// The part of a string before a `${`; the embedded expression's tokens follow
+    INTERPOLATION,
     NUMBER,
     // keywords
     BREAK,
     CONTINUE,
+    /// `incr x;` / `decr x by n;` adjust a numeric variable in place
+    INCR,
+    DECR,
+    /// `yield expr;` inside a function makes it a generator
+    YIELD,
+    /// `breakpoint;` pauses into a debug prompt when running interactively
+    BREAKPOINT,
+    /// Floor division: `a div b` evaluates to `floor(a / b)`. A keyword is used
+    /// instead of `//`, which the scanner already treats as a line comment.
+    DIV,
+    /// `assert cond, "message";`
+    ASSERT,
+    /// `switch (x) { case 1: ... default: ... }`
+    SWITCH,
+    CASE,
+    DEFAULT,
+    /// `do { ... } while (cond);` runs the body before checking the condition
+    DO,
+    /// `defer { ... }` runs the block when the enclosing block exits
+    DEFER,
     AND,
     CLASS,
     ELSE,
+    ENUM,
     FALSE,
     FUN,
     FOR,
@@ -80,7 +314,7 @@ pub enum TokenType {
     EOF,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone)]
 pub enum LiteralType{
     String(String),
     Number(f64),
@@ -88,6 +322,21 @@ pub enum LiteralType{
     Nil,
 }
 
+/// Literals are compared as syntax, not as Lox values: numbers match when
+/// their bits do, so a folded NaN equals itself and `Eq` agrees with `Hash`.
+/// Lox's `==` on numbers is IEEE equality; see `Interpreter::is_equal`.
+impl PartialEq for LiteralType {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (LiteralType::String(a), LiteralType::String(b)) => a == b,
+            (LiteralType::Number(a), LiteralType::Number(b)) => a.to_bits() == b.to_bits(),
+            (LiteralType::Bool(a), LiteralType::Bool(b)) => a == b,
+            (LiteralType::Nil, LiteralType::Nil) => true,
+            _ => false,
+        }
+    }
+}
+
 impl Eq for LiteralType {}
 
 impl Hash for LiteralType {
@@ -120,6 +369,8 @@ pub struct Token{
     pub type_: TokenType,
     pub lexeme: String,
     pub line: usize,
+    /// 1-based column of the token's first character (0 for synthetic tokens)
+    pub column: usize,
     pub literal: Option<LiteralType>,
 }
 
@@ -130,13 +381,14 @@ impl Hash for Token {
         self.type_.hash(state);
         self.lexeme.hash(state);
         self.line.hash(state);
+        self.column.hash(state);
         self.literal.hash(state);
     }
 }
 
 impl Token {
-    pub fn new(type_: TokenType, lexeme: String, line: usize, literal: Option<LiteralType>) -> Self{
-        Self {type_, lexeme, line, literal}
+    pub fn new(type_: TokenType, lexeme: String, line: usize, column: usize, literal: Option<LiteralType>) -> Self{
+        Self {type_, lexeme, line, column, literal}
     }
 
     pub fn to_string(&self) -> String{
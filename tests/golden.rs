@@ -0,0 +1,81 @@
+//! Golden tests: every `tests/golden/*.lox` file is run and its output compared
+//! with the `// expect: <line>` comments in it, in the style of the jlox test
+//! suite. A `// expect runtime error: <message>` comment means the script must
+//! stop with that runtime error after printing the expected lines.
+
+use lox::error::Error;
+use lox::interpreter::Interpreter;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+const EXPECT: &str = "// expect: ";
+const EXPECT_RUNTIME_ERROR: &str = "// expect runtime error: ";
+/// Same as the binary's interpreter thread, so scripts may recurse up to the
+/// call-depth limit without overflowing the test thread's small stack
+const STACK_SIZE: usize = 256 * 1024 * 1024;
+
+struct Expectation {
+    output: String,
+    runtime_error: Option<String>,
+}
+
+fn expectation(source: &str) -> Expectation {
+    let mut output = String::new();
+    let mut runtime_error = None;
+    for line in source.lines() {
+        if let Some(index) = line.find(EXPECT) {
+            output.push_str(&line[index + EXPECT.len()..]);
+            output.push('\n');
+        } else if let Some(index) = line.find(EXPECT_RUNTIME_ERROR) {
+            runtime_error = Some(line[index + EXPECT_RUNTIME_ERROR.len()..].to_string());
+        }
+    }
+    Expectation { output, runtime_error }
+}
+
+/// Run one script; `Err` describes how it differed from its expectations
+fn check(path: &Path) -> Result<(), String> {
+    let source = fs::read_to_string(path).map_err(|e| format!("can't read: {e}"))?;
+    let expected = expectation(&source);
+    let (output, error) = Interpreter::run_and_capture(&source);
+    if output != expected.output {
+        return Err(format!("expected output:\n{}got:\n{}", expected.output, output));
+    }
+    let runtime_error = match error {
+        None => None,
+        Some(Error::RuntimeError(error)) => Some(error.message),
+        Some(Error::SyntaxError(messages)) => return Err(format!("syntax errors: {messages:?}")),
+        Some(Error::ReturnError(_)) => return Err("unexpected top-level return".to_string()),
+    };
+    if runtime_error != expected.runtime_error {
+        return Err(format!("expected runtime error {:?}, got {:?}", expected.runtime_error, runtime_error));
+    }
+    Ok(())
+}
+
+#[test]
+fn golden_scripts() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden");
+    let mut scripts: Vec<PathBuf> = fs::read_dir(&dir)
+        .expect("tests/golden is missing")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "lox"))
+        .collect();
+    scripts.sort();
+    assert!(!scripts.is_empty(), "no scripts in {}", dir.display());
+    let total = scripts.len();
+
+    let failures: Vec<String> = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || {
+            scripts
+                .iter()
+                .filter_map(|path| check(path).err().map(|why| format!("{}: {}", path.display(), why)))
+                .collect()
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+    assert!(failures.is_empty(), "{} of {} golden scripts failed:\n{}", failures.len(), total, failures.join("\n"));
+}
@@ -0,0 +1,47 @@
+//! Embedders can change what `print` writes between and after its values.
+
+use lox::interpreter::Interpreter;
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+struct Capture(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Capture {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn run(interpreter: &mut Interpreter, source: &str) -> String {
+    let buffer = Rc::new(RefCell::new(Vec::new()));
+    interpreter.set_output(Box::new(Capture(Rc::clone(&buffer))));
+    interpreter.eval_source(source).expect("script should run");
+    String::from_utf8_lossy(&buffer.borrow()).into_owned()
+}
+
+#[test]
+fn defaults_are_space_and_newline() {
+    let mut interpreter = Interpreter::new();
+    assert_eq!(run(&mut interpreter, "print 1, \"a\", nil; print true;"), "1 a nil\ntrue\n");
+}
+
+#[test]
+fn custom_separator_and_terminator() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_print_separator(", ");
+    interpreter.set_print_terminator(";\n");
+    assert_eq!(run(&mut interpreter, "print 1, 2, 3; print \"x\";"), "1, 2, 3;\nx;\n");
+}
+
+#[test]
+fn empty_terminator_joins_prints() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_print_separator("");
+    interpreter.set_print_terminator("");
+    assert_eq!(run(&mut interpreter, "print \"a\", \"b\"; print \"c\";"), "abc");
+}
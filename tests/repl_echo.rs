@@ -0,0 +1,59 @@
+//! The REPL prints the value of each expression statement, except assignments.
+
+use lox::interpreter::Interpreter;
+use lox::parser::Parser;
+use lox::resolver::Resolver;
+use lox::scanner::Scanner;
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+struct Capture(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Capture {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Run each line as its own REPL input and return everything echoed
+fn repl(lines: &[&str]) -> String {
+    let buffer = Rc::new(RefCell::new(Vec::new()));
+    let mut interpreter = Interpreter::new();
+    interpreter.set_output(Box::new(Capture(Rc::clone(&buffer))));
+    for line in lines {
+        let tokens = Scanner::new(line).try_scan_tokens().expect("line should scan");
+        let statements = Parser::new(tokens).parse().expect("line should parse");
+        let mut resolver = Resolver::new(&mut interpreter);
+        resolver.resolve_statements(&statements);
+        assert!(resolver.errors.is_empty());
+        interpreter.interpret(&statements, true).expect("line should run");
+    }
+    String::from_utf8_lossy(&buffer.borrow()).into_owned()
+}
+
+#[test]
+fn expressions_are_echoed() {
+    assert_eq!(repl(&["1 + 2;", "var xs = [4];", "xs[0];"]), "3\n4\n");
+}
+
+#[test]
+fn assignments_are_not_echoed() {
+    let output = repl(&[
+        "var x = 1;",
+        "x = 2;",
+        "class C {}",
+        "var c = C();",
+        "c.field = 3;",
+        "var xs = [0];",
+        "xs[0] = 4;",
+        "var m = {};",
+        "m[\"k\"] = 5;",
+        "x;",
+    ]);
+    assert_eq!(output, "2\n");
+}
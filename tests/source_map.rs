@@ -0,0 +1,57 @@
+//! Tests for the offset <-> (line, column) conversions in `lox::source_map`.
+
+use lox::source_map::{line_starts, offset_to_line_col};
+
+#[test]
+fn empty_source_has_one_line() {
+    assert_eq!(line_starts(""), vec![0]);
+    assert_eq!(offset_to_line_col(&line_starts(""), 0), (1, 1));
+}
+
+#[test]
+fn single_line() {
+    let starts = line_starts("print 1;");
+    assert_eq!(starts, vec![0]);
+    assert_eq!(offset_to_line_col(&starts, 0), (1, 1));
+    assert_eq!(offset_to_line_col(&starts, 6), (1, 7));
+}
+
+#[test]
+fn multi_line_with_trailing_newline() {
+    let starts = line_starts("ab\ncd\n");
+    // The trailing newline starts a third, empty line
+    assert_eq!(starts, vec![0, 3, 6]);
+    assert_eq!(offset_to_line_col(&starts, 1), (1, 2));
+    assert_eq!(offset_to_line_col(&starts, 4), (2, 2));
+    assert_eq!(offset_to_line_col(&starts, 6), (3, 1));
+}
+
+#[test]
+fn offsets_at_line_boundaries() {
+    let starts = line_starts("ab\ncd\nef");
+    // The newline itself belongs to the line it ends
+    assert_eq!(offset_to_line_col(&starts, 2), (1, 3));
+    // The first character after it starts the next line
+    assert_eq!(offset_to_line_col(&starts, 3), (2, 1));
+    assert_eq!(offset_to_line_col(&starts, 5), (2, 3));
+    assert_eq!(offset_to_line_col(&starts, 6), (3, 1));
+    // One past the last character is still on the last line
+    assert_eq!(offset_to_line_col(&starts, 8), (3, 3));
+}
+
+#[test]
+fn blank_lines() {
+    let starts = line_starts("\n\nx");
+    assert_eq!(starts, vec![0, 1, 2]);
+    assert_eq!(offset_to_line_col(&starts, 0), (1, 1));
+    assert_eq!(offset_to_line_col(&starts, 1), (2, 1));
+    assert_eq!(offset_to_line_col(&starts, 2), (3, 1));
+}
+
+#[test]
+fn offsets_count_chars_not_bytes() {
+    let starts = line_starts("é\nü");
+    assert_eq!(starts, vec![0, 2]);
+    assert_eq!(offset_to_line_col(&starts, 1), (1, 2));
+    assert_eq!(offset_to_line_col(&starts, 2), (2, 1));
+}